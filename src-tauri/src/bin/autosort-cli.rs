@@ -0,0 +1,394 @@
+//! Headless command-line front end for AutoSort.
+//!
+//! Runs the same sorting engine as the desktop app without opening a window,
+//! so it can be used on servers, over SSH or from scripts.
+
+use autosort_lib::config::schema::{AfterExtract, Condition, RuleAction, SortRule};
+use autosort_lib::config::{load_config, save_config};
+use autosort_lib::engine::{recover_moves, validate_rule, History, MoveRecord, RecordKind, UndoOptions, UndoSelection};
+use autosort_lib::watcher::{validate_destinations, FileWatcher};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use clap::{ArgGroup, Parser, Subcommand};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Parser)]
+#[command(name = "autosort-cli", version, about = "Sort a folder using your AutoSort rules")]
+struct Cli {
+    /// Limit scan, dry-run and rules commands to one watch profile (default: all / the default profile)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Watch the configured folder and sort new files until interrupted
+    Watch,
+    /// Sort every file currently in the watch folder
+    Scan,
+    /// Show what a scan would do without moving anything
+    DryRun,
+    /// Archive files older than their rule's compaction age now
+    Compact,
+    /// List scheduled sweeps with their last and next run
+    Schedules,
+    /// Undo moves from history, by record id, batch, rule or time range
+    #[command(group(ArgGroup::new("target").required(true).args(["ids", "batch", "rule", "from"])))]
+    Undo {
+        /// Ids of the history records to undo
+        ids: Vec<String>,
+        /// Undo every move from one scan or watcher session
+        #[arg(long)]
+        batch: Option<String>,
+        /// Undo every move made by a rule
+        #[arg(long)]
+        rule: Option<String>,
+        /// Undo every move made since this time, e.g. "2024-06-01" or "2024-06-01 14:30"
+        /// (local time) or an RFC 3339 timestamp
+        #[arg(long, value_parser = parse_time)]
+        from: Option<DateTime<Utc>>,
+        /// End of the time range for --from (default: now)
+        #[arg(long, value_parser = parse_time, requires = "from")]
+        to: Option<DateTime<Utc>>,
+        /// Restore files even if they were changed after being moved
+        #[arg(long)]
+        force: bool,
+    },
+    /// Re-apply a move that was undone
+    Redo {
+        /// Id of the undo record, or of the move that was undone
+        id: String,
+    },
+    /// List recent moves
+    History {
+        /// Number of records to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Manage sorting rules
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum RulesCommand {
+    /// List all rules, highest priority first
+    List,
+    /// Add an extension-based rule
+    Add {
+        /// Display name of the rule
+        #[arg(long)]
+        name: String,
+        /// Comma-separated list of extensions, e.g. "pdf,docx"
+        #[arg(long, value_delimiter = ',', required = true)]
+        ext: Vec<String>,
+        /// Folder (relative to the destination root) to move matches into; may use
+        /// placeholders such as {year}, {month}, {ext}, {rule} or {regex.1}
+        #[arg(long)]
+        dest: String,
+        #[arg(long, default_value_t = 50)]
+        priority: i32,
+    },
+    /// Remove a rule by id or name
+    Remove {
+        rule: String,
+    },
+}
+
+fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let cli = Cli::parse();
+    match run(cli.command, cli.profile.as_deref()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command, profile: Option<&str>) -> Result<(), String> {
+    match command {
+        Command::Watch => watch(),
+        Command::Scan => scan(profile),
+        Command::DryRun => dry_run(profile),
+        Command::Compact => compact(profile),
+        Command::Schedules => schedules(),
+        Command::Undo { ids, batch, rule, from, to, force } => {
+            let selection = match (batch, rule, from) {
+                (Some(batch), _, _) => UndoSelection::Batch(batch),
+                (_, Some(rule), _) => UndoSelection::Rule(rule),
+                (_, _, Some(from)) => UndoSelection::TimeRange { from, to: to.unwrap_or_else(Utc::now) },
+                _ => UndoSelection::Ids(ids),
+            };
+            undo(&selection, force)
+        }
+        Command::Redo { id } => {
+            let config = load_config();
+            let history = History::new(config.history_limit);
+            let record = history.redo(&id, &config)?;
+            println!("Redone {} -> {}", record.original_path.display(), record.new_path.display());
+            Ok(())
+        }
+        Command::History { limit } => {
+            let config = load_config();
+            let history = History::new(config.history_limit);
+            for record in history.get_recent(limit) {
+                println!(
+                    "{}  {}  [{}]  {} -> {}{}{}",
+                    record.id,
+                    record.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                    record.rule_name,
+                    record.original_path.display(),
+                    record.new_path.display(),
+                    record.batch_id.as_ref().map(|b| format!("  (batch {})", b)).unwrap_or_default(),
+                    record_status(&record),
+                );
+            }
+            Ok(())
+        }
+        Command::Rules { command } => rules(command, profile),
+    }
+}
+
+/// Parse an RFC 3339 timestamp, or a local date with an optional time.
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|d| d.and_time(Default::default())))
+        .map_err(|_| format!("\"{}\" is not a date like 2024-06-01 or 2024-06-01 14:30", value))?;
+    Local.from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} doesn't exist in the local time zone", value))
+}
+
+fn record_status(record: &MoveRecord) -> String {
+    match (&record.kind, record.can_undo) {
+        (RecordKind::Undo { of }, true) => format!("  (undo of {})", of),
+        (RecordKind::Undo { .. }, false) => "  (undo, redone)".to_string(),
+        (RecordKind::Deleted, _) => "  (deleted)".to_string(),
+        (RecordKind::Compacted { entry }, true) => format!("  (archived as {})", entry),
+        (_, false) => "  (undone)".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn watch() -> Result<(), String> {
+    // Only a long-running process recovers the journal; other commands could otherwise
+    // roll back moves the desktop app is in the middle of
+    let _move_lock = recover_moves();
+    let config = load_config();
+    let history = Arc::new(History::new(config.history_limit));
+    let mut watcher = FileWatcher::new(config, history);
+
+    watcher.start_processor();
+    watcher.start()?;
+
+    // The watcher and processor run on their own threads; keep the process alive.
+    loop {
+        std::thread::sleep(Duration::from_secs(60));
+    }
+}
+
+fn scan(profile: Option<&str>) -> Result<(), String> {
+    let config = load_config();
+    let history = Arc::new(History::new(config.history_limit));
+    let watcher = FileWatcher::new(config, history);
+
+    let mut failed = 0;
+    for pending in watcher.scan_folder(profile) {
+        match watcher.move_now(&pending.id) {
+            Ok(()) => match pending.action {
+                RuleAction::Trash => println!("{} -> trash", pending.file_name),
+                RuleAction::Delete => println!("{} deleted", pending.file_name),
+                _ => println!("{} -> {}", pending.file_name, pending.destination),
+            },
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: {}", pending.file_name, e);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} file(s) could not be moved", failed));
+    }
+    Ok(())
+}
+
+fn undo(selection: &UndoSelection, force: bool) -> Result<(), String> {
+    let config = load_config();
+    let history = History::new(config.history_limit);
+
+    let outcomes = history.undo_many(selection, &UndoOptions::from_config(&config, force));
+    let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+    for outcome in &outcomes {
+        match (&outcome.restored_to, &outcome.error) {
+            (Some(path), None) => println!("Restored {}", path.display()),
+            (_, error) => println!("{} !! {}", outcome.id, error.as_deref().unwrap_or("Unknown error")),
+        }
+    }
+
+    if outcomes.is_empty() {
+        return Err("Nothing to undo".to_string());
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} moves could not be undone", failed, outcomes.len()));
+    }
+    Ok(())
+}
+
+fn dry_run(profile: Option<&str>) -> Result<(), String> {
+    let config = load_config();
+    let history = Arc::new(History::new(config.history_limit));
+    let watcher = FileWatcher::new(config, history);
+
+    for planned in watcher.plan_scan(profile) {
+        if let (None, Some(existing)) = (&planned.destination, &planned.duplicate_of) {
+            println!(
+                "{} == {}  [{}] (duplicate, would be deleted)",
+                planned.source.display(),
+                existing.display(),
+                planned.rule_name,
+            );
+            continue;
+        }
+        match (&planned.destination, &planned.error) {
+            (None, None) => println!(
+                "{}  [{}] (would be {})",
+                planned.source.display(),
+                planned.rule_name,
+                if planned.action == RuleAction::Trash { "moved to the trash" } else { "deleted" },
+            ),
+            (Some(destination), _) => println!(
+                "{} -> {}  [{}]",
+                planned.source.display(),
+                destination.display(),
+                planned.rule_name,
+            ),
+            (None, error) => println!(
+                "{} !! {}  [{}]",
+                planned.source.display(),
+                error.as_deref().unwrap_or("Unknown error"),
+                planned.rule_name,
+            ),
+        }
+    }
+    Ok(())
+}
+
+fn compact(profile: Option<&str>) -> Result<(), String> {
+    let config = load_config();
+    let history = Arc::new(History::new(config.history_limit));
+    let watcher = FileWatcher::new(config, history);
+
+    let summary = watcher.compact(profile);
+    for archive in &summary.archives {
+        println!("-> {}", archive.display());
+    }
+    println!("Archived {} files ({} bytes)", summary.files, summary.bytes);
+    for error in &summary.errors {
+        eprintln!("{}", error);
+    }
+
+    if !summary.errors.is_empty() {
+        return Err(format!("{} problem(s) during compaction", summary.errors.len()));
+    }
+    Ok(())
+}
+
+fn schedules() -> Result<(), String> {
+    let config = load_config();
+    let history = Arc::new(History::new(config.history_limit));
+    let watcher = FileWatcher::new(config, history);
+
+    let format_time = |time: Option<chrono::DateTime<chrono::Utc>>| {
+        time.map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "-".to_string())
+    };
+    for status in watcher.schedule_statuses() {
+        println!(
+            "{}  [{}]  {}  last {}  next {}{}{}",
+            status.name,
+            status.cron,
+            status.folder.display(),
+            format_time(status.last_run),
+            format_time(status.next_run),
+            if status.enabled { "" } else { "  (disabled)" },
+            status.error.map(|e| format!("  !! {}", e)).unwrap_or_default(),
+        );
+    }
+    Ok(())
+}
+
+fn rules(command: RulesCommand, profile: Option<&str>) -> Result<(), String> {
+    let mut config = load_config();
+    let rules = config.rules_mut(profile)
+        .ok_or_else(|| "Profile not found".to_string())?;
+
+    match command {
+        RulesCommand::List => {
+            let mut rules: Vec<&SortRule> = rules.iter().collect();
+            rules.sort_by_key(|r| std::cmp::Reverse(r.priority));
+            for rule in rules {
+                let target = match rule.action {
+                    RuleAction::Move => rule.destination_folder.clone(),
+                    action if action.uses_destination() => format!("{} ({:?})", rule.destination_folder, action),
+                    action => format!("({:?})", action),
+                };
+                println!(
+                    "{}  {:>4}  {}{}  -> {}",
+                    rule.id,
+                    rule.priority,
+                    rule.name,
+                    if rule.enabled { "" } else { " (disabled)" },
+                    target,
+                );
+            }
+            Ok(())
+        }
+        RulesCommand::Add { name, ext, dest, priority } => {
+            let rule = SortRule {
+                id: Uuid::new_v4().to_string(),
+                name,
+                enabled: true,
+                priority,
+                conditions: vec![Condition::Extension(
+                    ext.into_iter().map(|e| e.trim_start_matches('.').to_string()).collect(),
+                )],
+                destination_folder: dest,
+                is_default: false,
+                max_depth: None,
+                rename: Vec::new(),
+                action: RuleAction::Move,
+                after_extract: AfterExtract::Keep,
+                compaction: None,
+            };
+            validate_rule(&rule)?;
+            let id = rule.id.clone();
+            rules.push(rule);
+            config.all_profiles().iter().try_for_each(validate_destinations)?;
+            println!("Added rule {}", id);
+            save_config(&config)
+        }
+        RulesCommand::Remove { rule } => {
+            let original_len = rules.len();
+            rules.retain(|r| r.id != rule && r.name != rule);
+
+            if rules.len() == original_len {
+                return Err("Rule not found".to_string());
+            }
+            save_config(&config)
+        }
+    }
+}
@@ -1,7 +1,14 @@
-use crate::config::schema::{Config, SortRule};
+use crate::config::schema::{Config, SortRule, WatchProfile};
 use crate::config::{load_config, save_config};
-use crate::engine::{History, MoveRecord, HistoryStats, undo_move};
-use crate::watcher::{FileWatcher, PendingFile};
+use crate::engine::{
+    find_duplicates as find_duplicate_groups, resolve_duplicates as resolve_duplicate_groups, validate_rule,
+    CompactionSummary, DuplicateAction, DuplicateGroup, DuplicateOutcome, History, MoveRecord, HistoryStats, PlannedMove,
+    modified_since_move, MoveLock, UndoOptions, UndoOutcome, UndoSelection,
+};
+use crate::watcher::{
+    validate_destinations, validate_ignore_patterns, validate_schedule, ConflictChoice, ConflictOutcome, FileWatcher, PendingFile, ProfileStatus,
+    ScheduleStatus,
+};
 use parking_lot::Mutex;
 use std::sync::Arc;
 use tauri::State;
@@ -9,6 +16,8 @@ use tauri::State;
 pub struct AppState {
     pub watcher: Mutex<FileWatcher>,
     pub history: Arc<History>,
+    /// Held while the app runs so a CLI started alongside doesn't recover moves in progress
+    pub move_lock: Option<MoveLock>,
 }
 
 #[tauri::command]
@@ -18,6 +27,12 @@ pub fn get_config(_state: State<AppState>) -> Config {
 
 #[tauri::command]
 pub fn save_app_config(config: Config, state: State<AppState>) -> Result<(), String> {
+    for profile in config.all_profiles() {
+        profile.rules.iter().try_for_each(validate_rule)?;
+        validate_ignore_patterns(&profile.ignore_patterns)?;
+        validate_destinations(&profile)?;
+    }
+    config.schedules.iter().try_for_each(|s| validate_schedule(s, &config))?;
     save_config(&config)?;
     state.watcher.lock().update_config(config);
     Ok(())
@@ -52,18 +67,26 @@ pub fn get_watcher_status(state: State<AppState>) -> WatcherStatus {
     WatcherStatus {
         is_running: watcher.is_running(),
         is_paused: watcher.is_paused(),
+        profiles: watcher.profile_statuses(),
     }
 }
 
+/// Configured schedules with their last and next run times.
+#[tauri::command]
+pub fn get_schedules(state: State<AppState>) -> Vec<ScheduleStatus> {
+    state.watcher.lock().schedule_statuses()
+}
+
 #[derive(serde::Serialize)]
 pub struct WatcherStatus {
     pub is_running: bool,
     pub is_paused: bool,
+    pub profiles: Vec<ProfileStatus>,
 }
 
 #[tauri::command]
-pub fn get_pending_files(state: State<AppState>) -> Vec<PendingFile> {
-    state.watcher.lock().get_pending_files()
+pub fn get_pending_files(profile_id: Option<String>, state: State<AppState>) -> Vec<PendingFile> {
+    state.watcher.lock().get_pending_files(profile_id.as_deref())
 }
 
 #[tauri::command]
@@ -77,8 +100,54 @@ pub fn move_file_now(id: String, state: State<AppState>) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub fn scan_folder(state: State<AppState>) -> Vec<PendingFile> {
-    state.watcher.lock().scan_folder()
+pub fn resolve_conflict(
+    id: String,
+    choice: ConflictChoice,
+    apply_to_all: bool,
+    state: State<AppState>,
+) -> Vec<ConflictOutcome> {
+    state.watcher.lock().resolve_conflict(&id, choice, apply_to_all)
+}
+
+#[tauri::command]
+pub fn scan_folder(profile_id: Option<String>, state: State<AppState>) -> Vec<PendingFile> {
+    state.watcher.lock().scan_folder(profile_id.as_deref())
+}
+
+#[tauri::command]
+pub fn preview_scan(profile_id: Option<String>, state: State<AppState>) -> Vec<PlannedMove> {
+    state.watcher.lock().plan_scan(profile_id.as_deref())
+}
+
+/// Archive old files for rules with compaction now, instead of waiting for the daily run.
+#[tauri::command]
+pub fn compact_now(profile_id: Option<String>, state: State<AppState>) -> CompactionSummary {
+    state.watcher.lock().compact(profile_id.as_deref())
+}
+
+/// A profile, or the default profile when `profile_id` is `None`.
+fn find_profile(profile_id: Option<&str>) -> Result<WatchProfile, String> {
+    let config = load_config();
+    match profile_id {
+        Some(id) => config.profile(id).ok_or_else(|| "Profile not found".to_string()),
+        None => Ok(config.default_profile()),
+    }
+}
+
+#[tauri::command]
+pub fn find_duplicates(profile_id: Option<String>) -> Result<Vec<DuplicateGroup>, String> {
+    Ok(find_duplicate_groups(&find_profile(profile_id.as_deref())?))
+}
+
+#[tauri::command]
+pub fn resolve_duplicates(
+    groups: Vec<DuplicateGroup>,
+    action: DuplicateAction,
+    profile_id: Option<String>,
+    state: State<AppState>,
+) -> Result<Vec<DuplicateOutcome>, String> {
+    let profile = find_profile(profile_id.as_deref())?;
+    Ok(resolve_duplicate_groups(&groups, &action, &profile, &state.history))
 }
 
 #[tauri::command]
@@ -103,17 +172,46 @@ pub fn get_history_stats(state: State<AppState>) -> HistoryStats {
 }
 
 #[tauri::command]
-pub fn undo_file_move(id: String, state: State<AppState>) -> Result<(), String> {
+pub fn undo_file_move(id: String, force: Option<bool>, state: State<AppState>) -> Result<(), String> {
+    let options = UndoOptions::from_config(&load_config(), force.unwrap_or(false));
+    state.history.undo(&id, &options)?;
+    Ok(())
+}
+
+/// Warning to show before undoing a move whose file was changed after it was moved.
+#[tauri::command]
+pub fn check_undo(id: String, state: State<AppState>) -> Result<Option<String>, String> {
     let record = state.history.find(&id)
         .ok_or_else(|| "Record not found".to_string())?;
-    
-    if !record.can_undo {
-        return Err("This move has already been undone".to_string());
-    }
-    
-    undo_move(&record.new_path, &record.original_path)?;
-    state.history.mark_undone(&id);
-    Ok(())
+    Ok(modified_since_move(&record))
+}
+
+#[tauri::command]
+pub fn redo_file_move(id: String, state: State<AppState>) -> Result<MoveRecord, String> {
+    state.history.redo(&id, &load_config())
+}
+
+/// Step back through history: undo the newest move that is still in place.
+#[tauri::command]
+pub fn undo_last(force: Option<bool>, state: State<AppState>) -> Result<MoveRecord, String> {
+    let record = state.history.last_undoable()
+        .ok_or_else(|| "Nothing to undo".to_string())?;
+    let options = UndoOptions::from_config(&load_config(), force.unwrap_or(false));
+    state.history.undo(&record.id, &options)
+}
+
+/// Step forward again: redo the most recent undo.
+#[tauri::command]
+pub fn redo_last(state: State<AppState>) -> Result<MoveRecord, String> {
+    let record = state.history.last_redoable()
+        .ok_or_else(|| "Nothing to redo".to_string())?;
+    state.history.redo(&record.id, &load_config())
+}
+
+#[tauri::command]
+pub fn undo_moves(selection: UndoSelection, force: Option<bool>, state: State<AppState>) -> Vec<UndoOutcome> {
+    let options = UndoOptions::from_config(&load_config(), force.unwrap_or(false));
+    state.history.undo_many(&selection, &options)
 }
 
 #[tauri::command]
@@ -123,24 +221,35 @@ pub fn clear_history(state: State<AppState>) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub fn get_rules() -> Vec<SortRule> {
-    load_config().rules
+pub fn get_rules(profile_id: Option<String>) -> Result<Vec<SortRule>, String> {
+    let mut config = load_config();
+    config.rules_mut(profile_id.as_deref())
+        .map(|rules| rules.clone())
+        .ok_or_else(|| "Profile not found".to_string())
 }
 
 #[tauri::command]
-pub fn add_rule(rule: SortRule, state: State<AppState>) -> Result<(), String> {
+pub fn add_rule(rule: SortRule, profile_id: Option<String>, state: State<AppState>) -> Result<(), String> {
+    validate_rule(&rule)?;
     let mut config = load_config();
-    config.rules.push(rule);
+    config.rules_mut(profile_id.as_deref())
+        .ok_or_else(|| "Profile not found".to_string())?
+        .push(rule);
+    config.all_profiles().iter().try_for_each(validate_destinations)?;
     save_config(&config)?;
     state.watcher.lock().update_config(config);
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_rule(rule: SortRule, state: State<AppState>) -> Result<(), String> {
+pub fn update_rule(rule: SortRule, profile_id: Option<String>, state: State<AppState>) -> Result<(), String> {
+    validate_rule(&rule)?;
     let mut config = load_config();
-    if let Some(existing) = config.rules.iter_mut().find(|r| r.id == rule.id) {
+    let rules = config.rules_mut(profile_id.as_deref())
+        .ok_or_else(|| "Profile not found".to_string())?;
+    if let Some(existing) = rules.iter_mut().find(|r| r.id == rule.id) {
         *existing = rule;
+        config.all_profiles().iter().try_for_each(validate_destinations)?;
         save_config(&config)?;
         state.watcher.lock().update_config(config);
         Ok(())
@@ -150,12 +259,14 @@ pub fn update_rule(rule: SortRule, state: State<AppState>) -> Result<(), String>
 }
 
 #[tauri::command]
-pub fn delete_rule(id: String, state: State<AppState>) -> Result<(), String> {
+pub fn delete_rule(id: String, profile_id: Option<String>, state: State<AppState>) -> Result<(), String> {
     let mut config = load_config();
-    let original_len = config.rules.len();
-    config.rules.retain(|r| r.id != id);
+    let rules = config.rules_mut(profile_id.as_deref())
+        .ok_or_else(|| "Profile not found".to_string())?;
+    let original_len = rules.len();
+    rules.retain(|r| r.id != id);
     
-    if config.rules.len() < original_len {
+    if rules.len() < original_len {
         save_config(&config)?;
         state.watcher.lock().update_config(config);
         Ok(())
@@ -165,12 +276,14 @@ pub fn delete_rule(id: String, state: State<AppState>) -> Result<(), String> {
 }
 
 #[tauri::command]
-pub fn reorder_rules(rule_ids: Vec<String>, state: State<AppState>) -> Result<(), String> {
+pub fn reorder_rules(rule_ids: Vec<String>, profile_id: Option<String>, state: State<AppState>) -> Result<(), String> {
     let mut config = load_config();
+    let rules = config.rules_mut(profile_id.as_deref())
+        .ok_or_else(|| "Profile not found".to_string())?;
     
     // Create a new priority based on order
     for (index, id) in rule_ids.iter().enumerate() {
-        if let Some(rule) = config.rules.iter_mut().find(|r| r.id == *id) {
+        if let Some(rule) = rules.iter_mut().find(|r| r.id == *id) {
             rule.priority = (rule_ids.len() - index) as i32 * 10;
         }
     }
@@ -182,11 +295,13 @@ pub fn reorder_rules(rule_ids: Vec<String>, state: State<AppState>) -> Result<()
 
 #[tauri::command]
 pub fn test_rule(filename: String, rules: Vec<SortRule>) -> Option<String> {
-    use crate::engine::match_file;
+    use crate::engine::{expand_destination, match_file};
     use std::path::Path;
     
     let path = Path::new(&filename);
-    match_file(path, &rules).map(|r| r.destination_folder.clone())
+    match_file(path, &rules).map(|r| {
+        expand_destination(r, path).unwrap_or_else(|_| r.destination_folder.clone())
+    })
 }
 
 #[tauri::command]
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc, Weekday};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use uuid::Uuid;
@@ -17,6 +18,84 @@ pub struct Config {
     /// All-time count of files moved (persisted, not capped by history_limit)
     #[serde(default)]
     pub total_files_moved: u64,
+    /// Also sort files found in subfolders of the watch folder
+    #[serde(default)]
+    pub recursive: RecursiveOptions,
+    /// Extra folders to watch. The top-level watch folder settings above form the default profile.
+    #[serde(default)]
+    pub profiles: Vec<WatchProfile>,
+    /// After the grace period, how many polls in a row a file's size and modification time
+    /// must stay the same before it is moved (0 = move as soon as the grace period ends)
+    #[serde(default = "default_stable_checks")]
+    pub stable_checks: u32,
+    /// Gitignore-style patterns for files that are never sorted, e.g. partial downloads
+    #[serde(default = "default_ignore_patterns")]
+    pub ignore_patterns: Vec<String>,
+    /// Hash copies made when moving between drives and compare them with the source
+    /// before deleting it. Sizes are always compared.
+    #[serde(default)]
+    pub verify_copies: bool,
+    /// What undo does when the original location has been taken by another file
+    #[serde(default)]
+    pub undo_conflict: UndoConflict,
+    #[serde(default)]
+    pub extract_limits: ExtractLimits,
+    /// Recurring sweeps of a folder with a chosen set of rules
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+}
+
+/// A watched folder with its own rules, destination and conflict policy.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchProfile {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub watch_folder: PathBuf,
+    pub destination_root: PathBuf,
+    pub grace_period_seconds: u64,
+    pub rules: Vec<SortRule>,
+    pub conflict_resolution: ConflictResolution,
+    #[serde(default)]
+    pub recursive: RecursiveOptions,
+    #[serde(default = "default_ignore_patterns")]
+    pub ignore_patterns: Vec<String>,
+}
+
+/// A recurring job that runs some of a profile's rules against a folder, like
+/// "every Sunday at 03:00, sort Documents".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Cron expression in local time, either "min hour day month weekday" or with a
+    /// leading seconds field, e.g. "0 3 * * Sun"
+    pub cron: String,
+    /// Folder to sweep; relative paths are under the profile's destination root
+    pub folder: PathBuf,
+    /// Ids or names of the profile's rules to run. They run even if disabled, so a rule
+    /// can be kept for schedules only.
+    pub rules: Vec<String>,
+    /// Profile whose rules, destination root and conflict policy are used; the default
+    /// profile when `None`
+    #[serde(default)]
+    pub profile_id: Option<String>,
+    #[serde(default)]
+    pub recursive: RecursiveOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecursiveOptions {
+    pub enabled: bool,
+    /// How many folder levels below the watch folder to descend (0 = top level only)
+    pub max_depth: usize,
+    /// Subfolders to skip, either a name matched anywhere or a path relative to the watch folder.
+    /// Rule destination folders are always skipped.
+    pub exclude: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +107,132 @@ pub struct SortRule {
     pub conditions: Vec<Condition>,
     pub destination_folder: String,
     pub is_default: bool,
+    /// Only apply to files at most this many folders below the watch folder
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    /// Applied in order to the file name before it is moved
+    #[serde(default)]
+    pub rename: Vec<RenameTransform>,
+    #[serde(default)]
+    pub action: RuleAction,
+    /// What happens to the archive once an `Extract` rule has unpacked it
+    #[serde(default)]
+    pub after_extract: AfterExtract,
+    /// Periodically pack old files in the destination folder into monthly archives
+    #[serde(default)]
+    pub compaction: Option<Compaction>,
+}
+
+/// Packing files that have sat in a rule's destination folder for a while into one
+/// archive per month, named like "Images-2024-03.tar.zst".
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Compaction {
+    /// Files last modified more than this many days ago are archived
+    pub older_than_days: u64,
+    #[serde(default)]
+    pub format: CompactionFormat,
+    /// Folder under the destination root the archives are kept in
+    #[serde(default = "default_compaction_folder")]
+    pub archive_folder: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CompactionFormat {
+    #[default]
+    TarZst,
+    Zip,
+}
+
+impl CompactionFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            CompactionFormat::TarZst => "tar.zst",
+            CompactionFormat::Zip => "zip",
+        }
+    }
+}
+
+pub fn default_compaction_folder() -> String {
+    "Archive/Old".to_string()
+}
+
+/// What happens to a file that matches a rule.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RuleAction {
+    /// Move it into the destination folder
+    #[default]
+    Move,
+    /// Copy it into the destination folder and leave the original in place
+    Copy,
+    /// Hard link it into the destination folder; only works within one drive
+    Hardlink,
+    /// Put a symbolic link to it in the destination folder
+    Symlink,
+    /// Unpack a zip or tar archive into a folder named after it in the destination folder
+    Extract,
+    /// Move it to the system trash, where it can be restored from
+    Trash,
+    /// Delete it permanently
+    Delete,
+    /// Leave it where it is, so lower priority rules don't pick it up
+    Leave,
+}
+
+/// What an `Extract` rule does with the archive after unpacking it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "value")]
+pub enum AfterExtract {
+    /// Leave it where it is
+    #[default]
+    Keep,
+    /// Move it into this folder under the destination root; placeholders work as in
+    /// `destination_folder`
+    MoveTo(String),
+    /// Move it to the trash
+    Trash,
+}
+
+/// Limits that stop an extraction before a malicious archive fills the disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractLimits {
+    /// Most bytes an archive may unpack to
+    pub max_size: u64,
+    /// Most files and folders an archive may contain
+    pub max_entries: usize,
+    /// Most an archive may expand relative to its own size, e.g. 100 for 1 MB -> 100 MB
+    pub max_ratio: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 4 * 1024 * 1024 * 1024,
+            max_entries: 10_000,
+            max_ratio: 100,
+        }
+    }
+}
+
+impl RuleAction {
+    /// Whether files end up in the rule's destination folder.
+    pub fn uses_destination(self) -> bool {
+        !matches!(self, RuleAction::Trash | RuleAction::Delete | RuleAction::Leave)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum RenameTransform {
+    Lowercase,
+    Uppercase,
+    /// Replace every space with the given text
+    ReplaceSpaces(String),
+    /// Drop " (1)" / " - Copy" suffixes added to duplicate downloads
+    StripCopySuffix,
+    /// Prefix the file's modification date, formatted with a strftime string such as "%Y-%m-%d_"
+    PrefixDate(String),
+    RegexReplace { pattern: String, replacement: String },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +243,26 @@ pub enum Condition {
     NameRegex(String),
     SizeGreaterThan(u64),
     SizeLessThan(u64),
+    /// Last modified more than this many days ago
+    OlderThanDays(u64),
+    /// Last modified within this many days
+    NewerThanDays(u64),
+    ModifiedBefore(DateTime<Utc>),
+    ModifiedAfter(DateTime<Utc>),
+    CreatedBefore(DateTime<Utc>),
+    CreatedAfter(DateTime<Utc>),
+    /// Arrived (created, or modified if creation time is unknown) on one of these local weekdays
+    DayOfWeek(Vec<Weekday>),
+    /// Arrived between these local hours, `from` inclusive and `to` exclusive; wraps past midnight
+    HourBetween { from: u32, to: u32 },
+    /// Content type detected from the file's magic bytes, e.g. "application/pdf" or "image/*"
+    MimeType(String),
+    /// Every nested condition must match
+    All(Vec<Condition>),
+    /// At least one nested condition must match
+    Any(Vec<Condition>),
+    /// The nested condition must not match
+    Not(Box<Condition>),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -46,6 +271,24 @@ pub enum ConflictResolution {
     Skip,
     Overwrite,
     Ask,
+    /// Compare contents: an identical incoming file is dropped, a different one is renamed.
+    /// Dropped files go to `duplicates_folder` when set, otherwise they are deleted.
+    Deduplicate {
+        #[serde(default)]
+        duplicates_folder: Option<PathBuf>,
+    },
+}
+
+/// What undo does when something else now occupies a file's original location.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub enum UndoConflict {
+    /// Restore next to it as "name (1).ext"
+    #[default]
+    Rename,
+    /// Restore into this folder instead
+    RestoreTo(PathBuf),
+    /// Leave the file where it is and report an error
+    Fail,
 }
 
 impl Default for Config {
@@ -64,6 +307,93 @@ impl Default for Config {
             conflict_resolution: ConflictResolution::Rename,
             history_limit: 500,
             total_files_moved: 0,
+            recursive: RecursiveOptions::default(),
+            profiles: Vec::new(),
+            stable_checks: default_stable_checks(),
+            ignore_patterns: default_ignore_patterns(),
+            verify_copies: false,
+            undo_conflict: UndoConflict::default(),
+            extract_limits: ExtractLimits::default(),
+            schedules: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub const DEFAULT_PROFILE_ID: &'static str = "default";
+    
+    /// The profile described by the top-level watch folder settings.
+    pub fn default_profile(&self) -> WatchProfile {
+        WatchProfile {
+            id: Self::DEFAULT_PROFILE_ID.to_string(),
+            name: "Default".to_string(),
+            enabled: true,
+            watch_folder: self.watch_folder.clone(),
+            destination_root: self.destination_root.clone(),
+            grace_period_seconds: self.grace_period_seconds,
+            rules: self.rules.clone(),
+            conflict_resolution: self.conflict_resolution.clone(),
+            recursive: self.recursive.clone(),
+            ignore_patterns: self.ignore_patterns.clone(),
+        }
+    }
+    
+    /// The default profile followed by every extra profile.
+    pub fn all_profiles(&self) -> Vec<WatchProfile> {
+        let mut profiles = vec![self.default_profile()];
+        profiles.extend(self.profiles.iter().cloned());
+        profiles
+    }
+    
+    pub fn profile(&self, id: &str) -> Option<WatchProfile> {
+        if id == Self::DEFAULT_PROFILE_ID {
+            return Some(self.default_profile());
+        }
+        self.profiles.iter().find(|p| p.id == id).cloned()
+    }
+    
+    /// Rules of the given profile, or of the default profile when `profile_id` is `None`.
+    pub fn rules_mut(&mut self, profile_id: Option<&str>) -> Option<&mut Vec<SortRule>> {
+        match profile_id {
+            None => Some(&mut self.rules),
+            Some(id) if id == Self::DEFAULT_PROFILE_ID => Some(&mut self.rules),
+            Some(id) => self.profiles.iter_mut().find(|p| p.id == id).map(|p| &mut p.rules),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_stable_checks() -> u32 {
+    3
+}
+
+/// Hidden files and the partial/lock files browsers, download managers and Office leave behind.
+pub fn default_ignore_patterns() -> Vec<String> {
+    [
+        ".*",
+        "*.crdownload",
+        "*.part",
+        "*.tmp",
+        "*.download",
+        "*.opdownload",
+        "*.!qB",
+        "*.aria2",
+        "~$*",
+    ]
+    .iter()
+    .map(|p| p.to_string())
+    .collect()
+}
+
+impl Default for RecursiveOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_depth: 3,
+            exclude: Vec::new(),
         }
     }
 }
@@ -80,6 +410,11 @@ impl SortRule {
             )],
             destination_folder: destination.to_string(),
             is_default: true,
+            max_depth: None,
+            rename: Vec::new(),
+            action: RuleAction::Move,
+            after_extract: AfterExtract::Keep,
+            compaction: None,
         }
     }
 }
@@ -0,0 +1,540 @@
+use chrono::{DateTime, Datelike, Local, TimeZone, Timelike, Utc};
+use parking_lot::{Mutex, MutexGuard};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::config::schema::{Compaction, CompactionFormat, SortRule, UndoConflict, WatchProfile};
+use crate::engine::history::{modified_time, History, MoveRecord, RecordKind};
+use crate::engine::mover::{restore_target, sibling_temp_path};
+use crate::engine::template::static_prefix;
+
+/// Every archive rewrite reads an archive and swaps in an updated copy, so two at once
+/// would drop the entries one of them added. The mutex keeps threads apart, and a lock
+/// file other AutoSort processes.
+static ARCHIVE_LOCK: Mutex<()> = Mutex::new(());
+
+struct ArchiveGuard {
+    _thread: MutexGuard<'static, ()>,
+    _process: File,
+}
+
+fn lock_archives() -> Result<ArchiveGuard, String> {
+    let thread = ARCHIVE_LOCK.lock();
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("autosort");
+    fs::create_dir_all(&config_dir).ok();
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(config_dir.join("archives.lock"))
+        .and_then(|file| file.lock().map(|_| file))
+        .map_err(|e| format!("Failed to lock archives: {}", e))?;
+    Ok(ArchiveGuard { _thread: thread, _process: file })
+}
+
+/// What one compaction run did.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompactionSummary {
+    pub files: usize,
+    pub bytes: u64,
+    /// Archives that were created or added to
+    pub archives: Vec<PathBuf>,
+    pub errors: Vec<String>,
+}
+
+impl CompactionSummary {
+    pub fn merge(&mut self, other: CompactionSummary) {
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.archives.extend(other.archives);
+        self.errors.extend(other.errors);
+    }
+}
+
+/// Whether any enabled rule of `profile` has compaction turned on.
+pub fn has_compaction(profile: &WatchProfile) -> bool {
+    compacting_rules(profile).next().is_some()
+}
+
+fn compacting_rules(profile: &WatchProfile) -> impl Iterator<Item = (&SortRule, &Compaction)> {
+    profile.rules.iter()
+        .filter(|r| r.enabled && r.action.uses_destination())
+        .filter_map(|r| r.compaction.as_ref().map(|c| (r, c)))
+}
+
+/// Pack the old files in the destination folder of every rule of `profile` that has
+/// compaction turned on, then delete them. Each archived file gets its own history
+/// record, all sharing one batch id, so files can be restored one at a time.
+pub fn compact_profile(profile: &WatchProfile, history: &History) -> CompactionSummary {
+    let mut summary = CompactionSummary::default();
+    let batch_id = uuid::Uuid::new_v4().to_string();
+
+    for (rule, compaction) in compacting_rules(profile) {
+        // A template starting with a placeholder would mean archiving the whole root
+        let folder = static_prefix(&rule.destination_folder);
+        if folder.as_os_str().is_empty() {
+            summary.errors.push(format!(
+                "Rule \"{}\": compaction needs a destination folder that doesn't start with a placeholder",
+                rule.name
+            ));
+            continue;
+        }
+
+        let source_dir = profile.destination_root.join(folder);
+        let archive_dir = profile.destination_root.join(&compaction.archive_folder);
+        let cutoff = SystemTime::now() - Duration::from_secs(compaction.older_than_days * 24 * 60 * 60);
+
+        for (archive, files) in compaction_candidates(&source_dir, &archive_dir, rule, compaction, cutoff) {
+            // Taken before packing, so files written to meanwhile are kept
+            let packed: Vec<_> = files.iter().map(|(path, _)| file_state(path)).collect();
+            match archive_files(&archive, &files) {
+                Ok(entries) => {
+                    summary.archives.push(archive.clone());
+                    for (((path, _), entry), packed) in files.iter().zip(entries).zip(packed) {
+                        match remove_archived(path, packed, &archive, &entry, rule, &profile.id, &batch_id) {
+                            Ok(record) => {
+                                summary.files += 1;
+                                summary.bytes += record.file_size;
+                                history.add(record);
+                            }
+                            Err(e) => summary.errors.push(e),
+                        }
+                    }
+                }
+                Err(e) => summary.errors.push(format!("{}: {}", archive.display(), e)),
+            }
+        }
+    }
+
+    summary
+}
+
+/// Size and modification time of a file.
+fn file_state(path: &Path) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+/// Delete a file that is now safely in `archive` and describe that for the history.
+/// `packed` is the file's state when it was packed; if it changed since, the file is kept
+/// and its now outdated entry dropped from the archive instead.
+fn remove_archived(
+    path: &Path,
+    packed: Option<(u64, SystemTime)>,
+    archive: &Path,
+    entry: &str,
+    rule: &SortRule,
+    profile_id: &str,
+    batch_id: &str,
+) -> Result<MoveRecord, String> {
+    if packed.is_none() || file_state(path) != packed {
+        if let Err(e) = lock_archives().and_then(|_lock| rewrite_archive(archive, Some(entry), &[])) {
+            log::warn!("Failed to drop {} from {:?}: {}", entry, archive, e);
+        }
+        return Err(format!("{:?} changed while it was being archived, so it was kept", path));
+    }
+    let file_size = path.metadata().map(|m| m.len()).unwrap_or(0);
+    let modified = modified_time(path);
+    fs::remove_file(path)
+        .map_err(|e| format!("Archived {:?} but could not delete it: {}", path, e))?;
+
+    Ok(MoveRecord {
+        id: uuid::Uuid::new_v4().to_string(),
+        original_path: path.to_path_buf(),
+        new_path: archive.to_path_buf(),
+        rule_name: rule.name.clone(),
+        timestamp: Utc::now(),
+        file_size,
+        can_undo: true,
+        original_name: path.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        new_name: archive.file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        kind: RecordKind::Compacted { entry: entry.to_string() },
+        batch_id: Some(batch_id.to_string()),
+        modified,
+        profile_id: Some(profile_id.to_string()),
+    })
+}
+
+/// Files under `source_dir` last modified before `cutoff`, grouped by the monthly archive
+/// in `archive_dir` they belong in and paired with their name inside it. Hidden files and
+/// anything inside `archive_dir` are left alone.
+fn compaction_candidates(
+    source_dir: &Path,
+    archive_dir: &Path,
+    rule: &SortRule,
+    compaction: &Compaction,
+    cutoff: SystemTime,
+) -> BTreeMap<PathBuf, Vec<(PathBuf, String)>> {
+    let mut groups: BTreeMap<PathBuf, Vec<(PathBuf, String)>> = BTreeMap::new();
+    let rule_name: String = rule.name.chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect();
+
+    let mut dirs = vec![source_dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if hidden || path.starts_with(archive_dir) {
+                continue;
+            }
+            if file_type.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+
+            let Some(modified) = entry.metadata().ok().and_then(|m| m.modified().ok()) else {
+                continue;
+            };
+            if modified >= cutoff {
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(source_dir) else {
+                continue;
+            };
+            let name = relative.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            let month = DateTime::<Local>::from(modified).format("%Y-%m");
+            let archive = archive_dir.join(format!("{}-{}.{}", rule_name, month, compaction.format.extension()));
+            groups.entry(archive).or_default().push((path, name));
+        }
+    }
+
+    groups
+}
+
+/// Add `files` to `archive`, creating it if needed. Each file is paired with the name it
+/// should have inside the archive; names already taken get a number, like "a (1).txt".
+/// Returns the names used. The archive is rewritten under a temporary name and swapped in,
+/// so a failure leaves the old one untouched.
+pub fn archive_files(archive: &Path, files: &[(PathBuf, String)]) -> Result<Vec<String>, String> {
+    if let Some(parent) = archive.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+    let _lock = lock_archives()?;
+    rewrite_archive(archive, None, files)
+}
+
+/// Undo compaction of one file: extract `entry` from `archive` back to `original_location`
+/// and drop it from the archive. Returns where the file was restored to.
+pub fn restore_from_archive(
+    archive: &Path,
+    entry: &str,
+    original_location: &Path,
+    conflict: &UndoConflict,
+) -> Result<PathBuf, String> {
+    let _lock = lock_archives()?;
+    if !archive.exists() {
+        return Err("Archive no longer exists".to_string());
+    }
+
+    let target = restore_target(original_location, conflict)?;
+    let temp = sibling_temp_path(&target);
+    extract_entry(archive, entry, &temp)
+        .and_then(|_| fs::rename(&temp, &target).map_err(|e| e.to_string()))
+        .map_err(|e| {
+            fs::remove_file(&temp).ok();
+            format!("Failed to restore file: {}", e)
+        })?;
+
+    // The file is back either way; a stale copy in the archive only costs space
+    if let Err(e) = rewrite_archive(archive, Some(entry), &[]) {
+        log::warn!("Restored {:?} but could not remove it from {:?}: {}", target, archive, e);
+    }
+    Ok(target)
+}
+
+fn format_of(archive: &Path) -> CompactionFormat {
+    let name = archive.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+    if name.ends_with(".zip") {
+        CompactionFormat::Zip
+    } else {
+        CompactionFormat::TarZst
+    }
+}
+
+/// Copy `archive` without the entry `skip` and with `add` appended, then put the copy in
+/// its place. An archive left with no entries is deleted.
+fn rewrite_archive(archive: &Path, skip: Option<&str>, add: &[(PathBuf, String)]) -> Result<Vec<String>, String> {
+    let temp = sibling_temp_path(archive);
+    let written = match format_of(archive) {
+        CompactionFormat::Zip => rewrite_zip(archive, &temp, skip, add),
+        CompactionFormat::TarZst => rewrite_tar_zst(archive, &temp, skip, add),
+    };
+
+    match written {
+        Ok((_, 0)) => {
+            fs::remove_file(&temp).ok();
+            fs::remove_file(archive).ok();
+            Ok(Vec::new())
+        }
+        Ok((added, _)) => fs::rename(&temp, archive)
+            .map(|_| added)
+            .map_err(|e| {
+                fs::remove_file(&temp).ok();
+                e.to_string()
+            }),
+        Err(e) => {
+            fs::remove_file(&temp).ok();
+            Err(e)
+        }
+    }
+}
+
+/// Returns the names given to `add` and the number of entries written.
+fn rewrite_zip(
+    archive: &Path,
+    temp: &Path,
+    skip: Option<&str>,
+    add: &[(PathBuf, String)],
+) -> Result<(Vec<String>, usize), String> {
+    let mut writer = zip::ZipWriter::new(fs::File::create(temp).map_err(|e| e.to_string())?);
+    let mut names = HashSet::new();
+
+    if archive.exists() {
+        let file = fs::File::open(archive).map_err(|e| e.to_string())?;
+        let mut existing = zip::ZipArchive::new(io::BufReader::new(file)).map_err(|e| e.to_string())?;
+        for i in 0..existing.len() {
+            let entry = existing.by_index_raw(i).map_err(|e| e.to_string())?;
+            if Some(entry.name()) == skip {
+                continue;
+            }
+            names.insert(entry.name().to_string());
+            writer.raw_copy_file(entry).map_err(|e| e.to_string())?;
+        }
+    }
+
+    let mut added = Vec::new();
+    for (path, name) in add {
+        let name = unique_entry_name(name, &names);
+        let meta = path.metadata().map_err(|e| e.to_string())?;
+        let mut options = zip::write::SimpleFileOptions::default().large_file(meta.len() > u32::MAX as u64);
+        if let Some(time) = meta.modified().ok().and_then(zip_time) {
+            options = options.last_modified_time(time);
+        }
+
+        writer.start_file(name.as_str(), options).map_err(|e| e.to_string())?;
+        let mut source = fs::File::open(path).map_err(|e| e.to_string())?;
+        io::copy(&mut source, &mut writer).map_err(|e| e.to_string())?;
+        names.insert(name.clone());
+        added.push(name);
+    }
+
+    let file = writer.finish().map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())?;
+    Ok((added, names.len()))
+}
+
+/// Returns the names given to `add` and the number of entries written.
+fn rewrite_tar_zst(
+    archive: &Path,
+    temp: &Path,
+    skip: Option<&str>,
+    add: &[(PathBuf, String)],
+) -> Result<(Vec<String>, usize), String> {
+    let file = fs::File::create(temp).map_err(|e| e.to_string())?;
+    let encoder = zstd::Encoder::new(file, 0).map_err(|e| e.to_string())?;
+    let mut builder = tar::Builder::new(encoder);
+    let mut names = HashSet::new();
+
+    if archive.exists() {
+        let file = fs::File::open(archive).map_err(|e| e.to_string())?;
+        let decoder = zstd::Decoder::new(file).map_err(|e| e.to_string())?;
+        let mut existing = tar::Archive::new(decoder);
+        for entry in existing.entries().map_err(|e| e.to_string())? {
+            let mut entry = entry.map_err(|e| e.to_string())?;
+            let name = entry.path().map_err(|e| e.to_string())?.to_string_lossy().into_owned();
+            if Some(name.as_str()) == skip {
+                continue;
+            }
+            let mut header = entry.header().clone();
+            builder.append_data(&mut header, &name, &mut entry).map_err(|e| e.to_string())?;
+            names.insert(name);
+        }
+    }
+
+    let mut added = Vec::new();
+    for (path, name) in add {
+        let name = unique_entry_name(name, &names);
+        builder.append_path_with_name(path, &name).map_err(|e| e.to_string())?;
+        names.insert(name.clone());
+        added.push(name);
+    }
+
+    let encoder = builder.into_inner().map_err(|e| e.to_string())?;
+    let file = encoder.finish().map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())?;
+    Ok((added, names.len()))
+}
+
+/// Write the entry `name` of `archive` to a new file at `target`, with its modification
+/// time.
+fn extract_entry(archive: &Path, name: &str, target: &Path) -> Result<(), String> {
+    let file = fs::File::open(archive).map_err(|e| e.to_string())?;
+    let create = || fs::OpenOptions::new().write(true).create_new(true).open(target);
+
+    let modified = match format_of(archive) {
+        CompactionFormat::Zip => {
+            let mut existing = zip::ZipArchive::new(io::BufReader::new(file)).map_err(|e| e.to_string())?;
+            let mut entry = existing.by_name(name)
+                .map_err(|_| format!("{} is no longer in the archive", name))?;
+            io::copy(&mut entry, &mut create().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            entry.last_modified().and_then(from_zip_time)
+        }
+        CompactionFormat::TarZst => {
+            let decoder = zstd::Decoder::new(file).map_err(|e| e.to_string())?;
+            let mut existing = tar::Archive::new(decoder);
+            let mut entries = existing.entries().map_err(|e| e.to_string())?;
+            let mut entry = loop {
+                let entry = entries.next()
+                    .ok_or_else(|| format!("{} is no longer in the archive", name))?
+                    .map_err(|e| e.to_string())?;
+                if entry.path().is_ok_and(|p| p.to_string_lossy() == name) {
+                    break entry;
+                }
+            };
+            io::copy(&mut entry, &mut create().map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+            entry.header().mtime().ok().map(|t| filetime::FileTime::from_unix_time(t as i64, 0))
+        }
+    };
+
+    if let Some(mtime) = modified {
+        filetime::set_file_mtime(target, mtime).ok();
+    }
+    Ok(())
+}
+
+fn unique_entry_name(name: &str, taken: &HashSet<String>) -> String {
+    if !taken.contains(name) {
+        return name.to_string();
+    }
+    let (dir, file) = match name.rsplit_once('/') {
+        Some((dir, file)) => (format!("{}/", dir), file),
+        None => (String::new(), name),
+    };
+    let (stem, extension) = match file.rfind('.') {
+        Some(i) if i > 0 => (&file[..i], &file[i..]),
+        _ => (file, ""),
+    };
+    (1..)
+        .map(|n| format!("{}{} ({}){}", dir, stem, n, extension))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap_or_default()
+}
+
+/// Zip stores local time with two-second precision.
+fn zip_time(time: SystemTime) -> Option<zip::DateTime> {
+    let t = DateTime::<Local>::from(time);
+    zip::DateTime::from_date_and_time(
+        u16::try_from(t.year()).ok()?,
+        t.month() as u8,
+        t.day() as u8,
+        t.hour() as u8,
+        t.minute() as u8,
+        (t.second() / 2 * 2) as u8,
+    )
+    .ok()
+}
+
+fn from_zip_time(time: zip::DateTime) -> Option<filetime::FileTime> {
+    let t = Local
+        .with_ymd_and_hms(
+            time.year() as i32,
+            time.month() as u32,
+            time.day() as u32,
+            time.hour() as u32,
+            time.minute() as u32,
+            time.second() as u32,
+        )
+        .earliest()?;
+    Some(filetime::FileTime::from_unix_time(t.timestamp(), 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_and_restore_single_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        fs::create_dir_all(root.join("Images/2020")).unwrap();
+        let mtime = filetime::FileTime::from_unix_time(1_600_000_000, 0);
+        for name in ["a.jpg", "2020/a.jpg"] {
+            fs::write(root.join("Images").join(name), name).unwrap();
+            filetime::set_file_mtime(root.join("Images").join(name), mtime).unwrap();
+        }
+
+        for format in [CompactionFormat::TarZst, CompactionFormat::Zip] {
+            let archive = root.join(format!("Old/Images-2020-09.{}", format.extension()));
+            let files = vec![
+                (root.join("Images/a.jpg"), "a.jpg".to_string()),
+                (root.join("Images/2020/a.jpg"), "2020/a.jpg".to_string()),
+            ];
+            assert_eq!(archive_files(&archive, &files).unwrap(), vec!["a.jpg", "2020/a.jpg"]);
+            // Adding a name that's taken numbers it
+            assert_eq!(archive_files(&archive, &files[..1]).unwrap(), vec!["a (1).jpg"]);
+
+            let restored = root.join("restored.jpg");
+            restore_from_archive(&archive, "2020/a.jpg", &restored, &UndoConflict::Rename).unwrap();
+            assert_eq!(fs::read(&restored).unwrap(), b"2020/a.jpg");
+            let restored_mtime = filetime::FileTime::from_last_modification_time(&restored.metadata().unwrap());
+            assert_eq!(restored_mtime, mtime);
+            assert!(extract_entry(&archive, "2020/a.jpg", &root.join("gone.jpg")).is_err());
+
+            restore_from_archive(&archive, "a.jpg", &root.join("x.jpg"), &UndoConflict::Rename).unwrap();
+            restore_from_archive(&archive, "a (1).jpg", &root.join("y.jpg"), &UndoConflict::Rename).unwrap();
+            assert!(!archive.exists());
+            fs::remove_file(&restored).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_file_changed_while_archiving_is_kept() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        let rule = SortRule::new("Documents", vec!["pdf"], "Documents", 10);
+        let archive = root.join("Old/Documents-2020-09.zip");
+        let files = vec![
+            (root.join("edited.pdf"), "edited.pdf".to_string()),
+            (root.join("old.pdf"), "old.pdf".to_string()),
+        ];
+        for (path, _) in &files {
+            fs::write(path, b"contents").unwrap();
+        }
+
+        let packed: Vec<_> = files.iter().map(|(path, _)| file_state(path)).collect();
+        let entries = archive_files(&archive, &files).unwrap();
+        fs::write(&files[0].0, b"new contents").unwrap();
+
+        assert!(remove_archived(&files[0].0, packed[0], &archive, &entries[0], &rule, "default", "batch").is_err());
+        assert_eq!(fs::read(&files[0].0).unwrap(), b"new contents");
+        assert!(extract_entry(&archive, &entries[0], &root.join("x.pdf")).is_err());
+
+        let record = remove_archived(&files[1].0, packed[1], &archive, &entries[1], &rule, "default", "batch").unwrap();
+        assert_eq!(record.kind, RecordKind::Compacted { entry: "old.pdf".to_string() });
+        assert!(!files[1].0.exists());
+    }
+}
@@ -0,0 +1,290 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::config::schema::WatchProfile;
+use crate::engine::hash::{files_identical, hash_file};
+use crate::engine::history::{History, MoveRecord, RecordKind};
+use crate::engine::mover::link_duplicate;
+
+/// Rule name used for history records of duplicate cleanups.
+const CLEANUP_RULE_NAME: &str = "Duplicate cleanup";
+
+/// Files under the destination root that have identical contents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    pub size: u64,
+    pub files: Vec<DuplicateFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateFile {
+    pub path: PathBuf,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+/// What to do with each duplicate group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum DuplicateAction {
+    /// Keep the most recently modified copy and delete the rest
+    KeepNewest,
+    /// Keep the copy inside this folder (relative to the destination root) and delete
+    /// the rest; groups without a copy there are left alone
+    KeepInFolder(PathBuf),
+    /// Keep one copy and replace the others with hard links to it
+    Hardlink,
+}
+
+/// Result of applying an action to one file of a group.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateOutcome {
+    pub path: PathBuf,
+    pub kept: Option<PathBuf>,
+    pub history_id: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Index every file under the profile's destination root by size, then hash only the
+/// sizes that occur more than once. Empty files, hidden folders and compaction archive
+/// folders are ignored.
+pub fn find_duplicates(profile: &WatchProfile) -> Vec<DuplicateGroup> {
+    let archives: Vec<PathBuf> = profile.rules.iter()
+        .filter_map(|rule| rule.compaction.as_ref())
+        .map(|compaction| profile.destination_root.join(&compaction.archive_folder))
+        .collect();
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    collect_files(&profile.destination_root, &archives, &mut by_size);
+
+    let mut groups = Vec::new();
+    for (size, paths) in by_size {
+        if size == 0 || paths.len() < 2 {
+            continue;
+        }
+
+        let mut by_hash: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for path in dedupe_links(paths) {
+            match hash_file(&path) {
+                Ok(hash) => by_hash.entry(hash).or_default().push(path),
+                Err(e) => log::warn!("Failed to hash {:?}: {}", path, e),
+            }
+        }
+
+        for (hash, mut paths) in by_hash {
+            if paths.len() < 2 {
+                continue;
+            }
+            paths.sort();
+            let files = paths.into_iter()
+                .map(|path| DuplicateFile {
+                    modified: fs::metadata(&path).and_then(|m| m.modified()).ok().map(DateTime::from),
+                    path,
+                })
+                .collect();
+            groups.push(DuplicateGroup { hash, size, files });
+        }
+    }
+
+    // Biggest savings first
+    groups.sort_by(|a, b| {
+        let wasted = |g: &DuplicateGroup| g.size * (g.files.len() as u64 - 1);
+        wasted(b).cmp(&wasted(a)).then_with(|| a.files[0].path.cmp(&b.files[0].path))
+    });
+    groups
+}
+
+/// Apply `action` to every group and record each change in `history`.
+pub fn resolve_duplicates(
+    groups: &[DuplicateGroup],
+    action: &DuplicateAction,
+    profile: &WatchProfile,
+    history: &History,
+) -> Vec<DuplicateOutcome> {
+    let mut outcomes = Vec::new();
+    let batch_id = Uuid::new_v4().to_string();
+
+    for group in groups {
+        let Some(keep) = choose_kept(group, action, &profile.destination_root) else {
+            outcomes.extend(group.files.iter().map(|f| DuplicateOutcome {
+                path: f.path.clone(),
+                kept: None,
+                history_id: None,
+                error: Some("No copy in the preferred folder".to_string()),
+            }));
+            continue;
+        };
+
+        for file in group.files.iter().filter(|f| f.path != keep) {
+            let result = resolve_one(&file.path, &keep, action, group.size, &batch_id, &profile.id);
+            let mut outcome = DuplicateOutcome {
+                path: file.path.clone(),
+                kept: Some(keep.clone()),
+                history_id: None,
+                error: None,
+            };
+            match result {
+                Ok(record) => {
+                    outcome.history_id = Some(record.id.clone());
+                    history.add(record);
+                }
+                Err(e) => outcome.error = Some(e),
+            }
+            outcomes.push(outcome);
+        }
+    }
+
+    outcomes
+}
+
+fn choose_kept(group: &DuplicateGroup, action: &DuplicateAction, destination_root: &Path) -> Option<PathBuf> {
+    match action {
+        DuplicateAction::KeepNewest | DuplicateAction::Hardlink => group.files.iter()
+            .max_by_key(|f| f.modified)
+            .map(|f| f.path.clone()),
+        DuplicateAction::KeepInFolder(folder) => {
+            let folder = destination_root.join(folder);
+            group.files.iter()
+                .find(|f| f.path.starts_with(&folder))
+                .map(|f| f.path.clone())
+        }
+    }
+}
+
+fn resolve_one(
+    duplicate: &Path,
+    keep: &Path,
+    action: &DuplicateAction,
+    size: u64,
+    batch_id: &str,
+    profile_id: &str,
+) -> Result<MoveRecord, String> {
+    // The index may be stale; only touch files that are still identical
+    if !files_identical(duplicate, keep).map_err(|e| format!("Failed to compare files: {}", e))? {
+        return Err("File changed since the scan".to_string());
+    }
+
+    let kind = match action {
+        DuplicateAction::Hardlink => {
+            link_duplicate(duplicate, keep)?;
+            RecordKind::DuplicateLinked { target: keep.to_path_buf() }
+        }
+        _ => {
+            fs::remove_file(duplicate).map_err(|e| format!("Failed to delete duplicate: {}", e))?;
+            RecordKind::Duplicate { duplicate_of: keep.to_path_buf(), deleted: true }
+        }
+    };
+
+    let name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    Ok(MoveRecord {
+        id: Uuid::new_v4().to_string(),
+        original_path: duplicate.to_path_buf(),
+        new_path: keep.to_path_buf(),
+        rule_name: CLEANUP_RULE_NAME.to_string(),
+        timestamp: Utc::now(),
+        file_size: size,
+        can_undo: true,
+        original_name: name(duplicate),
+        new_name: name(keep),
+        kind,
+        batch_id: Some(batch_id.to_string()),
+        modified: None,
+        profile_id: Some(profile_id.to_string()),
+    })
+}
+
+fn collect_files(dir: &Path, skip: &[PathBuf], by_size: &mut HashMap<u64, Vec<PathBuf>>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("Failed to read {:?}: {}", dir, e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+
+        if file_type.is_dir() {
+            if !entry.file_name().to_string_lossy().starts_with('.') && !skip.contains(&path) {
+                collect_files(&path, skip, by_size);
+            }
+        } else if file_type.is_file() {
+            if let Ok(meta) = entry.metadata() {
+                by_size.entry(meta.len()).or_default().push(path);
+            }
+        }
+    }
+}
+
+/// Drop paths that are already hard links to a file earlier in the list, so linked
+/// copies aren't reported again.
+#[cfg(unix)]
+fn dedupe_links(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    use std::collections::HashSet;
+    use std::os::unix::fs::MetadataExt;
+
+    let mut seen = HashSet::new();
+    paths.into_iter()
+        .filter(|p| match fs::metadata(p) {
+            Ok(meta) => seen.insert((meta.dev(), meta.ino())),
+            Err(_) => false,
+        })
+        .collect()
+}
+
+#[cfg(not(unix))]
+fn dedupe_links(paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::schema::{Compaction, CompactionFormat, Config};
+
+    #[test]
+    fn test_find_duplicates_groups_identical_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        fs::create_dir_all(root.join("Documents")).unwrap();
+        fs::create_dir_all(root.join("Images")).unwrap();
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::create_dir_all(root.join("Archive/Old")).unwrap();
+        fs::write(root.join("Documents/report.pdf"), b"same").unwrap();
+        fs::write(root.join("Images/scan.pdf"), b"same").unwrap();
+        fs::write(root.join(".hidden/copy.pdf"), b"same").unwrap();
+        fs::write(root.join("Archive/Old/packed.pdf"), b"same").unwrap();
+        fs::write(root.join("Documents/other.pdf"), b"diff").unwrap();
+
+        let mut config = Config { destination_root: root.clone(), ..Config::default() };
+        config.rules[0].compaction = Some(Compaction {
+            older_than_days: 90,
+            format: CompactionFormat::Zip,
+            archive_folder: "Archive/Old".to_string(),
+        });
+        let profile = config.default_profile();
+
+        let groups = find_duplicates(&profile);
+        assert_eq!(groups.len(), 1);
+        let paths: Vec<_> = groups[0].files.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(paths, vec![root.join("Documents/report.pdf"), root.join("Images/scan.pdf")]);
+
+        let action = DuplicateAction::KeepInFolder("Images".into());
+        assert_eq!(choose_kept(&groups[0], &action, &root), Some(root.join("Images/scan.pdf")));
+        let action = DuplicateAction::KeepInFolder("Videos".into());
+        assert_eq!(choose_kept(&groups[0], &action, &root), None);
+
+        let history = History::at(root.join("history.json"), 100);
+        let outcomes = resolve_duplicates(&groups, &DuplicateAction::KeepNewest, &profile, &history);
+        let id = outcomes[0].history_id.clone().unwrap();
+        let record = history.get_all().into_iter().find(|r| r.id == id).unwrap();
+        assert_eq!(record.profile_id.as_deref(), Some(profile.id.as_str()));
+    }
+}
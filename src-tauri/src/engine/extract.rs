@@ -0,0 +1,341 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use crate::config::schema::{AfterExtract, ConflictResolution, ExtractLimits};
+use crate::engine::mover::{get_unique_path, move_file, MoveStatus};
+use crate::engine::trash::trash_file;
+
+/// Below this many unpacked bytes the compression ratio isn't checked; small archives of
+/// empty or repetitive files compress extremely well without being dangerous.
+const RATIO_FLOOR: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+/// Result of a successful extraction.
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    /// Folder holding everything that was unpacked
+    pub folder: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// `name` without its archive extension, or `None` if it isn't a supported archive.
+pub fn archive_stem(name: &str) -> Option<&str> {
+    let (stem, _) = split_archive_name(name)?;
+    Some(stem)
+}
+
+fn split_archive_name(name: &str) -> Option<(&str, ArchiveFormat)> {
+    let lower = name.to_lowercase();
+    let (extension, format) = [
+        (".tar.gz", ArchiveFormat::TarGz),
+        (".tgz", ArchiveFormat::TarGz),
+        (".tar", ArchiveFormat::Tar),
+        (".zip", ArchiveFormat::Zip),
+    ]
+    .into_iter()
+    .find(|(extension, _)| lower.ends_with(extension))?;
+
+    let stem = &name[..name.len() - extension.len()];
+    if stem.is_empty() {
+        return None;
+    }
+    Some((stem, format))
+}
+
+/// Unpack `archive` into a new folder `folder_name` inside `dest_dir`, numbered like
+/// "name (1)" if that is taken. If the archive holds a single top-level folder, that
+/// folder's contents go straight into the new one instead of being nested. Everything is
+/// unpacked into a hidden temporary folder first and renamed into place at the end, so a
+/// failed or rejected archive leaves nothing behind.
+///
+/// Entries that would land outside the folder are rejected, and so is the whole archive
+/// once it exceeds `limits`. Links inside archives are skipped.
+pub fn extract_archive(
+    archive: &Path,
+    dest_dir: &Path,
+    folder_name: &str,
+    limits: &ExtractLimits,
+) -> Result<Extraction, String> {
+    let name = archive.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let (_, format) = split_archive_name(name)
+        .ok_or_else(|| format!("{} is not a zip or tar archive", name))?;
+    let archive_size = archive.metadata().map_err(|e| e.to_string())?.len();
+
+    fs::create_dir_all(dest_dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+    let mut folder = dest_dir.join(folder_name);
+    if folder.exists() {
+        folder = get_unique_path(&folder, |p| p.exists());
+    }
+    let temp = dest_dir.join(format!(".{}.{}.autosort-tmp", folder_name, uuid::Uuid::new_v4()));
+    fs::create_dir(&temp).map_err(|e| format!("Failed to create directory: {}", e))?;
+
+    let mut budget = Budget { limits, archive_size, bytes: 0, entries: 0, files: 0 };
+    let unpacked = fs::File::open(archive)
+        .map_err(|e| format!("Failed to open archive: {}", e))
+        .and_then(|file| match format {
+            ArchiveFormat::Zip => unpack_zip(file, &temp, &mut budget),
+            ArchiveFormat::Tar => unpack_tar(file, &temp, &mut budget),
+            ArchiveFormat::TarGz => unpack_tar(flate2::read::GzDecoder::new(file), &temp, &mut budget),
+        })
+        .and_then(|_| {
+            let contents = single_folder(&temp).unwrap_or_else(|| temp.clone());
+            fs::rename(&contents, &folder).map_err(|e| e.to_string())
+        });
+    fs::remove_dir(&temp).ok();
+
+    if let Err(e) = unpacked {
+        fs::remove_dir_all(&temp).ok();
+        return Err(format!("Failed to extract {}: {}", name, e));
+    }
+    Ok(Extraction { folder, files: budget.files, bytes: budget.bytes })
+}
+
+/// The only entry in `dir`, if that is a folder.
+fn single_folder(dir: &Path) -> Option<PathBuf> {
+    let mut entries = fs::read_dir(dir).ok()?;
+    let only = entries.next()?.ok()?;
+    if entries.next().is_some() || !only.file_type().ok()?.is_dir() {
+        return None;
+    }
+    Some(only.path())
+}
+
+/// Deal with the archive after it has been extracted. Returns where it went, or `None`
+/// if it was kept. Relative `MoveTo` folders are resolved against `destination_root`.
+pub fn dispose_archive(
+    archive: &Path,
+    after: &AfterExtract,
+    destination_root: &Path,
+    conflict_resolution: &ConflictResolution,
+    verify_hash: bool,
+) -> Result<Option<PathBuf>, String> {
+    match after {
+        AfterExtract::Keep => Ok(None),
+        AfterExtract::Trash => trash_file(archive, verify_hash).map(Some),
+        AfterExtract::MoveTo(folder) => {
+            let file_name = archive.file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| "Invalid file name".to_string())?;
+            let result = move_file(archive, destination_root, folder, file_name, conflict_resolution, verify_hash);
+            match result.status {
+                MoveStatus::Moved => Ok(Some(result.destination)),
+                MoveStatus::AlreadyInPlace => Ok(None),
+                _ => Err(result.error.unwrap_or_else(|| "Unknown error".to_string())),
+            }
+        }
+    }
+}
+
+/// Undo `extract_archive` by deleting the folder it created.
+pub fn remove_extracted(folder: &Path) -> Result<(), String> {
+    match fs::symlink_metadata(folder) {
+        Err(_) => Ok(()),
+        Ok(meta) if !meta.is_dir() => Err(format!("{:?} is no longer a folder", folder)),
+        Ok(_) => fs::remove_dir_all(folder)
+            .map_err(|e| format!("Failed to remove {:?}: {}", folder, e)),
+    }
+}
+
+/// The first file or folder under `folder` modified after `since`, i.e. added or edited
+/// after the extraction that created it.
+pub fn changed_since(folder: &Path, since: SystemTime) -> Option<PathBuf> {
+    let mut dirs = vec![folder.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            if meta.modified().is_ok_and(|modified| modified > since) {
+                return Some(entry.path());
+            }
+            if meta.is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    None
+}
+
+/// Running totals checked against the limits after every entry and while writing.
+struct Budget<'a> {
+    limits: &'a ExtractLimits,
+    archive_size: u64,
+    bytes: u64,
+    entries: usize,
+    files: usize,
+}
+
+impl Budget<'_> {
+    fn add_entry(&mut self) -> Result<(), String> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(format!("Archive has more than {} entries", self.limits.max_entries));
+        }
+        Ok(())
+    }
+
+    /// Bytes that may still be written before a limit is hit.
+    fn remaining(&self) -> u64 {
+        let by_ratio = self.archive_size
+            .saturating_mul(self.limits.max_ratio)
+            .max(RATIO_FLOOR);
+        self.limits.max_size.min(by_ratio).saturating_sub(self.bytes)
+    }
+
+    fn limit_error(&self) -> String {
+        format!(
+            "Archive unpacks to more than {} bytes or {}x its size",
+            self.limits.max_size, self.limits.max_ratio
+        )
+    }
+
+    /// Copy one file out of the archive, stopping as soon as it goes over budget. Sizes
+    /// recorded in archive headers can lie, so only the bytes actually read count.
+    fn write_file(&mut self, reader: &mut impl Read, target: &Path) -> Result<(), String> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(target)
+            .map_err(|e| format!("Failed to create {:?}: {}", target, e))?;
+
+        let remaining = self.remaining();
+        let written = io::copy(&mut reader.take(remaining.saturating_add(1)), &mut file)
+            .map_err(|e| e.to_string())?;
+        if written > remaining {
+            return Err(self.limit_error());
+        }
+        self.bytes += written;
+        self.files += 1;
+        Ok(())
+    }
+}
+
+/// Where an archive entry goes under `root`, refusing absolute paths and `..` so the
+/// entry can't escape the extraction folder ("zip slip").
+fn entry_target(root: &Path, entry: &Path) -> Result<PathBuf, String> {
+    let mut target = root.to_path_buf();
+    for component in entry.components() {
+        match component {
+            Component::Normal(part) => target.push(part),
+            Component::CurDir => {}
+            _ => return Err(format!("Archive entry {:?} points outside the folder", entry)),
+        }
+    }
+    // Backslashes aren't separators on Unix, but are for whoever opens the files later
+    if entry.to_string_lossy().split(['/', '\\']).any(|part| part == "..") {
+        return Err(format!("Archive entry {:?} points outside the folder", entry));
+    }
+    Ok(target)
+}
+
+fn unpack_zip(file: fs::File, root: &Path, budget: &mut Budget) -> Result<(), String> {
+    let mut archive = zip::ZipArchive::new(io::BufReader::new(file)).map_err(|e| e.to_string())?;
+
+    // Reject obvious bombs before writing anything; `write_file` catches lying headers
+    let declared: u64 = (0..archive.len())
+        .filter_map(|i| archive.by_index_raw(i).ok().map(|entry| entry.size()))
+        .sum();
+    if archive.len() > budget.limits.max_entries || declared > budget.remaining() {
+        return Err(budget.limit_error());
+    }
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+        budget.add_entry()?;
+        let name = PathBuf::from(entry.name());
+        let target = entry_target(root, &name)?;
+
+        if entry.is_dir() {
+            fs::create_dir_all(&target).map_err(|e| e.to_string())?;
+        } else if entry.is_symlink() {
+            log::warn!("Skipping link {:?} in archive", name);
+        } else {
+            budget.write_file(&mut entry, &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn unpack_tar(reader: impl Read, root: &Path, budget: &mut Budget) -> Result<(), String> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        budget.add_entry()?;
+        let name = entry.path().map_err(|e| e.to_string())?.into_owned();
+        let target = entry_target(root, &name)?;
+        let entry_type = entry.header().entry_type();
+
+        if entry_type.is_dir() {
+            fs::create_dir_all(&target).map_err(|e| e.to_string())?;
+        } else if entry_type.is_file() {
+            budget.write_file(&mut entry, &target)?;
+            if let Ok(mtime) = entry.header().mtime() {
+                filetime::set_file_mtime(&target, filetime::FileTime::from_unix_time(mtime as i64, 0)).ok();
+            }
+        } else {
+            log::warn!("Skipping {:?} entry {:?} in archive", entry_type, name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+        for (name, contents) in entries {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_rejects_zip_slip_and_bombs() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        let limits = ExtractLimits::default();
+
+        write_zip(&root.join("data.zip"), &[("a.txt", b"a"), ("docs/b.txt", b"b")]);
+        let extraction = extract_archive(&root.join("data.zip"), &root.join("out"), "data", &limits).unwrap();
+        assert_eq!(extraction.folder, root.join("out/data"));
+        assert_eq!(fs::read(root.join("out/data/docs/b.txt")).unwrap(), b"b");
+        assert_eq!(extraction.files, 2);
+
+        write_zip(&root.join("nested.zip"), &[("nested/c.txt", b"c")]);
+        extract_archive(&root.join("nested.zip"), &root.join("out"), "data", &limits).unwrap();
+        assert_eq!(fs::read(root.join("out/data (1)/c.txt")).unwrap(), b"c");
+
+        write_zip(&root.join("evil.zip"), &[("ok.txt", b"ok"), ("../escaped.txt", b"x")]);
+        assert!(extract_archive(&root.join("evil.zip"), &root.join("out"), "evil", &limits).is_err());
+        assert!(!root.join("out/escaped.txt").exists());
+        assert!(!root.join("out/evil").exists());
+
+        let zeros = vec![0u8; 64 * 1024];
+        write_zip(&root.join("bomb.zip"), &[("zeros", &zeros)]);
+        let tight = ExtractLimits { max_size: 1024, ..ExtractLimits::default() };
+        assert!(extract_archive(&root.join("bomb.zip"), &root.join("out"), "bomb", &tight).is_err());
+        assert_eq!(fs::read_dir(root.join("out")).unwrap().count(), 2);
+
+        remove_extracted(&extraction.folder).unwrap();
+        assert!(!extraction.folder.exists());
+    }
+}
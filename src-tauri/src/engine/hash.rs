@@ -0,0 +1,33 @@
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// SHA-256 of a file's contents as a hex string, read in fixed-size chunks so large
+/// files never have to fit in memory.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Whether two files have the same contents. Sizes are compared first so differing
+/// files are usually rejected without reading them.
+pub fn files_identical(a: &Path, b: &Path) -> io::Result<bool> {
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    Ok(hash_file(a)? == hash_file(b)?)
+}
@@ -2,9 +2,55 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use parking_lot::RwLock;
 
+use crate::config::schema::{
+    AfterExtract, Config, ConflictResolution, ExtractLimits, RuleAction, UndoConflict, WatchProfile,
+};
+use crate::engine::compact::{archive_files, restore_from_archive};
+use crate::engine::extract::{archive_stem, changed_since, dispose_archive, extract_archive, remove_extracted};
+use crate::engine::hash::files_identical;
+use crate::engine::mover::{
+    link_duplicate, mirror_file, move_file, remove_mirror, restore_duplicate, undo_move, unlink_duplicate,
+};
+use crate::engine::trash::{restore_from_trash, trash_file};
+
+/// What happened to the file of a history record.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum RecordKind {
+    #[default]
+    Move,
+    /// The file was identical to `duplicate_of` and was dropped. When `deleted`,
+    /// `new_path` is the kept file rather than a copy of the dropped one.
+    Duplicate { duplicate_of: PathBuf, deleted: bool },
+    /// The file at `original_path` was replaced by a hard link to the identical `target`.
+    DuplicateLinked { target: PathBuf },
+    /// The file was copied to `new_path` and the original left in place.
+    Copy,
+    /// `new_path` was created as a hard link to the file at `original_path`.
+    Hardlink,
+    /// `new_path` was created as a symbolic link to the file at `original_path`.
+    Symlink,
+    /// The file was moved to the trash. `new_path` is where it is in the trash, or the
+    /// original path on platforms where that isn't known.
+    Trashed,
+    /// The file was deleted permanently and cannot be restored.
+    Deleted,
+    /// The archive at `original_path` was unpacked into the folder `new_path`, then moved
+    /// to `archive_moved_to` (in the trash if `archive_trashed`) unless it was kept.
+    Extracted {
+        archive_moved_to: Option<PathBuf>,
+        archive_trashed: bool,
+    },
+    /// The file was packed into the archive `new_path` as `entry` and then deleted.
+    Compacted { entry: String },
+    /// Undid the record with id `of`, moving the file from `original_path` back to
+    /// `new_path`. While `can_undo` is set the undone record can be redone.
+    Undo { of: String },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MoveRecord {
     pub id: String,
@@ -14,6 +60,61 @@ pub struct MoveRecord {
     pub timestamp: DateTime<Utc>,
     pub file_size: u64,
     pub can_undo: bool,
+    /// File name before the move, and after any rename transforms
+    #[serde(default)]
+    pub original_name: String,
+    #[serde(default)]
+    pub new_name: String,
+    #[serde(default)]
+    pub kind: RecordKind,
+    /// Shared by every move from the same scan, watcher session or cleanup
+    #[serde(default)]
+    pub batch_id: Option<String>,
+    /// Modification time (Unix seconds) of the file right after the move, used to tell
+    /// whether it was edited before an undo
+    #[serde(default)]
+    pub modified: Option<i64>,
+    /// Profile whose rule made the change; older records don't have it
+    #[serde(default)]
+    pub profile_id: Option<String>,
+}
+
+/// How an undo restores files.
+#[derive(Debug, Clone, Default)]
+pub struct UndoOptions {
+    pub conflict: UndoConflict,
+    pub verify_hash: bool,
+    /// Restore even if the file was changed after it was moved
+    pub force: bool,
+}
+
+impl UndoOptions {
+    pub fn from_config(config: &Config, force: bool) -> Self {
+        Self {
+            conflict: config.undo_conflict.clone(),
+            verify_hash: config.verify_copies,
+            force,
+        }
+    }
+}
+
+/// Which records a batch undo applies to.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum UndoSelection {
+    Ids(Vec<String>),
+    Batch(String),
+    Rule(String),
+    TimeRange { from: DateTime<Utc>, to: DateTime<Utc> },
+}
+
+/// Result of undoing one record as part of a batch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoOutcome {
+    pub id: String,
+    /// Where the file was restored; differs from the original path after a conflict
+    pub restored_to: Option<PathBuf>,
+    pub error: Option<String>,
 }
 
 fn get_history_path() -> PathBuf {
@@ -24,10 +125,9 @@ fn get_history_path() -> PathBuf {
     config_dir.join("history.json")
 }
 
-fn load_history_from_disk(limit: usize) -> VecDeque<MoveRecord> {
-    let path = get_history_path();
+fn load_history_from_disk(path: &Path, limit: usize) -> VecDeque<MoveRecord> {
     if path.exists() {
-        match fs::read_to_string(&path) {
+        match fs::read_to_string(path) {
             Ok(content) => {
                 match serde_json::from_str::<Vec<MoveRecord>>(&content) {
                     Ok(records) => {
@@ -48,12 +148,11 @@ fn load_history_from_disk(limit: usize) -> VecDeque<MoveRecord> {
     VecDeque::with_capacity(limit)
 }
 
-fn save_history_to_disk(records: &VecDeque<MoveRecord>) {
-    let path = get_history_path();
+fn save_history_to_disk(path: &Path, records: &VecDeque<MoveRecord>) {
     let vec: Vec<&MoveRecord> = records.iter().collect();
     match serde_json::to_string_pretty(&vec) {
         Ok(content) => {
-            if let Err(e) = fs::write(&path, content) {
+            if let Err(e) = fs::write(path, content) {
                 log::error!("Failed to write history: {}", e);
             }
         }
@@ -66,26 +165,34 @@ fn save_history_to_disk(records: &VecDeque<MoveRecord>) {
 pub struct History {
     records: RwLock<VecDeque<MoveRecord>>,
     limit: usize,
+    path: PathBuf,
 }
 
 impl History {
     pub fn new(limit: usize) -> Self {
-        let records = load_history_from_disk(limit);
+        Self::at(get_history_path(), limit)
+    }
+    
+    /// History kept in `path` instead of the config folder.
+    pub(crate) fn at(path: PathBuf, limit: usize) -> Self {
         Self {
-            records: RwLock::new(records),
+            records: RwLock::new(load_history_from_disk(&path, limit)),
             limit,
+            path,
         }
     }
     
     pub fn add(&self, record: MoveRecord) {
         let mut records = self.records.write();
-        
+        self.push(&mut records, record);
+        save_history_to_disk(&self.path, &records);
+    }
+    
+    fn push(&self, records: &mut VecDeque<MoveRecord>, record: MoveRecord) {
         if records.len() >= self.limit {
             records.pop_back();
         }
-        
         records.push_front(record);
-        save_history_to_disk(&records);
     }
     
     pub fn get_all(&self) -> Vec<MoveRecord> {
@@ -100,18 +207,207 @@ impl History {
         self.records.read().iter().find(|r| r.id == id).cloned()
     }
     
+    /// Whether `archive` was unpacked by an extraction that hasn't been undone and kept the
+    /// archive where it was, so sorting it again would only unpack it a second time. An
+    /// archive of a different size at the same path is a new one.
+    pub fn already_extracted(&self, archive: &Path) -> bool {
+        let Ok(meta) = archive.metadata() else {
+            return false;
+        };
+        self.records.read().iter().any(|r| {
+            r.can_undo
+                && r.original_path == archive
+                && r.file_size == meta.len()
+                && matches!(r.kind, RecordKind::Extracted { archive_moved_to: None, .. })
+        })
+    }
+    
     pub fn mark_undone(&self, id: &str) {
         let mut records = self.records.write();
         if let Some(record) = records.iter_mut().find(|r| r.id == id) {
             record.can_undo = false;
         }
-        save_history_to_disk(&records);
+        save_history_to_disk(&self.path, &records);
+    }
+    
+    /// Move the file of a recorded move back to where it came from, mark the record undone
+    /// and add an `Undo` record that `redo` can reverse. Returns the `Undo` record, whose
+    /// `new_path` is where the file was restored. Files changed since the move are only
+    /// restored with `options.force`.
+    pub fn undo(&self, id: &str, options: &UndoOptions) -> Result<MoveRecord, String> {
+        let record = self.find(id)
+            .ok_or_else(|| "Record not found".to_string())?;
+        
+        match record.kind {
+            RecordKind::Undo { .. } => return Err("This is an undo; redo it instead".to_string()),
+            RecordKind::Deleted => return Err("Deleted files cannot be restored".to_string()),
+            _ => {}
+        }
+        if !record.can_undo {
+            return Err("This move has already been undone".to_string());
+        }
+        if !options.force {
+            if let Some(warning) = modified_since_move(&record) {
+                return Err(format!("{}. Undo with force to restore it anyway.", warning));
+            }
+        }
+        
+        let restored = match &record.kind {
+            RecordKind::Duplicate { duplicate_of, deleted: true } => {
+                restore_duplicate(duplicate_of, &record.original_path, &options.conflict, options.verify_hash)?
+            }
+            RecordKind::DuplicateLinked { target } => {
+                unlink_duplicate(&record.original_path, target)?;
+                record.original_path.clone()
+            }
+            RecordKind::Copy | RecordKind::Hardlink | RecordKind::Symlink => {
+                let action = record.kind.mirror_action().unwrap_or(RuleAction::Copy);
+                remove_mirror(&record.new_path, &record.original_path, action)?;
+                record.original_path.clone()
+            }
+            RecordKind::Trashed => {
+                restore_from_trash(&record.new_path, &record.original_path, &options.conflict, options.verify_hash)?
+            }
+            RecordKind::Extracted { archive_moved_to, archive_trashed } => {
+                remove_extracted(&record.new_path)?;
+                match archive_moved_to {
+                    Some(archive) if *archive_trashed => {
+                        restore_from_trash(archive, &record.original_path, &options.conflict, options.verify_hash)?
+                    }
+                    Some(archive) => undo_move(archive, &record.original_path, &options.conflict, options.verify_hash)?,
+                    None => record.original_path.clone(),
+                }
+            }
+            RecordKind::Compacted { entry } => {
+                restore_from_archive(&record.new_path, entry, &record.original_path, &options.conflict)?
+            }
+            _ => undo_move(&record.new_path, &record.original_path, &options.conflict, options.verify_hash)?,
+        };
+        
+        let undo_record = MoveRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            original_path: record.new_path.clone(),
+            new_path: restored.clone(),
+            rule_name: record.rule_name.clone(),
+            timestamp: Utc::now(),
+            file_size: record.file_size,
+            can_undo: true,
+            original_name: record.new_name.clone(),
+            new_name: restored
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            kind: RecordKind::Undo { of: record.id.clone() },
+            batch_id: record.batch_id.clone(),
+            modified: None,
+            profile_id: record.profile_id.clone(),
+        };
+        self.replace_active(id, undo_record.clone());
+        Ok(undo_record)
+    }
+    
+    /// Re-apply a move that was undone. `id` may be the undo record or the undone record.
+    /// Moves go through the conflict policy of the profile that made them again, since the
+    /// destination may have been taken in the meantime. Adds a fresh record for the
+    /// re-applied move and returns it.
+    pub fn redo(&self, id: &str, config: &Config) -> Result<MoveRecord, String> {
+        let undo_record = self.records.read()
+            .iter()
+            .find(|r| r.id == id || matches!(&r.kind, RecordKind::Undo { of } if of == id))
+            .cloned()
+            .ok_or_else(|| "Record not found".to_string())?;
+        
+        let RecordKind::Undo { of } = &undo_record.kind else {
+            return Err("This move has not been undone".to_string());
+        };
+        if !undo_record.can_undo {
+            return Err("This undo has already been redone".to_string());
+        }
+        let original = self.find(of)
+            .ok_or_else(|| "The undone move is no longer in history".to_string())?;
+        
+        let conflict_resolution = record_profile(&original, config).conflict_resolution;
+        let (new_path, kind) = reapply(&original, &undo_record.new_path, &conflict_resolution, config.verify_copies)?;
+        let redone = MoveRecord {
+            id: uuid::Uuid::new_v4().to_string(),
+            original_path: undo_record.new_path.clone(),
+            timestamp: Utc::now(),
+            can_undo: true,
+            modified: modified_time(&new_path),
+            new_name: new_path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            new_path,
+            kind,
+            ..original
+        };
+        self.replace_active(&undo_record.id, redone.clone());
+        Ok(redone)
+    }
+    
+    /// Newest record that `undo` would accept, for walking back through the stack.
+    pub fn last_undoable(&self) -> Option<MoveRecord> {
+        self.records.read()
+            .iter()
+            .find(|r| r.can_undo && !matches!(r.kind, RecordKind::Undo { .. }))
+            .cloned()
+    }
+    
+    /// Newest undo that `redo` would accept, for walking forward again.
+    pub fn last_redoable(&self) -> Option<MoveRecord> {
+        self.records.read()
+            .iter()
+            .find(|r| r.can_undo && matches!(r.kind, RecordKind::Undo { .. }))
+            .cloned()
+    }
+    
+    /// Mark `id` as no longer active and put `record` on top of the stack.
+    fn replace_active(&self, id: &str, record: MoveRecord) {
+        let mut records = self.records.write();
+        if let Some(old) = records.iter_mut().find(|r| r.id == id) {
+            old.can_undo = false;
+        }
+        self.push(&mut records, record);
+        save_history_to_disk(&self.path, &records);
+    }
+    
+    /// Undo every selected record, newest first so files moved more than once end up
+    /// where they started. A failure is reported for that record and the rest carry on.
+    pub fn undo_many(&self, selection: &UndoSelection, options: &UndoOptions) -> Vec<UndoOutcome> {
+        let ids: Vec<String> = match selection {
+            UndoSelection::Ids(ids) => {
+                let records = self.records.read();
+                let mut ids = ids.clone();
+                // Unknown ids go last; `undo` reports them as not found
+                ids.sort_by_key(|id| records.iter().position(|r| r.id == *id).unwrap_or(usize::MAX));
+                ids
+            }
+            _ => self.records.read()
+                .iter()
+                .filter(|r| r.can_undo && !matches!(r.kind, RecordKind::Undo { .. }) && selection.matches(r))
+                .map(|r| r.id.clone())
+                .collect(),
+        };
+        
+        ids.into_iter().map(|id| match self.undo(&id, options) {
+            Ok(undo_record) => UndoOutcome {
+                id,
+                restored_to: Some(undo_record.new_path),
+                error: None,
+            },
+            Err(e) => UndoOutcome {
+                id,
+                restored_to: None,
+                error: Some(e),
+            },
+        }).collect()
     }
     
     pub fn clear(&self) {
         let mut records = self.records.write();
         records.clear();
-        save_history_to_disk(&records);
+        save_history_to_disk(&self.path, &records);
     }
     
     pub fn stats(&self) -> HistoryStats {
@@ -120,11 +416,14 @@ impl History {
         let today_start = now.date_naive().and_hms_opt(0, 0, 0).unwrap();
         let week_start = now - chrono::Duration::days(7);
         
-        let today_count = records.iter()
+        // Undos aren't moves the user asked for, so they don't count
+        let moves = || records.iter().filter(|r| !matches!(r.kind, RecordKind::Undo { .. }));
+        
+        let today_count = moves()
             .filter(|r| r.timestamp.naive_utc() >= today_start)
             .count();
         
-        let week_count = records.iter()
+        let week_count = moves()
             .filter(|r| r.timestamp >= week_start)
             .count();
         
@@ -136,9 +435,240 @@ impl History {
     }
 }
 
+impl RecordKind {
+    /// The action that made a copy or link of the file, for records that did.
+    fn mirror_action(&self) -> Option<RuleAction> {
+        match self {
+            RecordKind::Copy => Some(RuleAction::Copy),
+            RecordKind::Hardlink => Some(RuleAction::Hardlink),
+            RecordKind::Symlink => Some(RuleAction::Symlink),
+            _ => None,
+        }
+    }
+}
+
+impl UndoSelection {
+    fn matches(&self, record: &MoveRecord) -> bool {
+        match self {
+            UndoSelection::Ids(ids) => ids.contains(&record.id),
+            UndoSelection::Batch(batch_id) => record.batch_id.as_ref() == Some(batch_id),
+            UndoSelection::Rule(rule_name) => record.rule_name == *rule_name,
+            UndoSelection::TimeRange { from, to } => record.timestamp >= *from && record.timestamp <= *to,
+        }
+    }
+}
+
+/// The profile that made the change `record` describes. Records from before profiles
+/// were recorded belong to the profile whose watch folder the file came from.
+fn record_profile(record: &MoveRecord, config: &Config) -> WatchProfile {
+    record.profile_id.as_deref()
+        .and_then(|id| config.profile(id))
+        .or_else(|| {
+            config.all_profiles()
+                .into_iter()
+                .filter(|p| record.original_path.starts_with(&p.watch_folder))
+                .max_by_key(|p| p.watch_folder.components().count())
+        })
+        .unwrap_or_else(|| config.default_profile())
+}
+
+/// Modification time of a file as Unix seconds.
+pub fn modified_time(path: &Path) -> Option<i64> {
+    let modified = path.metadata().ok()?.modified().ok()?;
+    Some(DateTime::<Utc>::from(modified).timestamp())
+}
+
+/// Why restoring `record` might lose work: the moved file's size or modification time
+/// no longer match what was recorded right after the move, or files in an extracted
+/// folder, which undo deletes, were added or edited since.
+pub fn modified_since_move(record: &MoveRecord) -> Option<String> {
+    if let RecordKind::Extracted { .. } = &record.kind {
+        return changed_since(&record.new_path, record.timestamp.into()).map(|path| {
+            format!("{} was added or changed after the archive was extracted", path.display())
+        });
+    }
+    let moved_file = match &record.kind {
+        RecordKind::Move | RecordKind::Copy | RecordKind::Duplicate { deleted: false, .. } => &record.new_path,
+        _ => return None,
+    };
+    let meta = moved_file.metadata().ok()?;
+    
+    if meta.len() != record.file_size {
+        return Some(format!(
+            "{} changed size since it was moved ({} -> {} bytes)",
+            record.new_name, record.file_size, meta.len()
+        ));
+    }
+    match (record.modified, modified_time(moved_file)) {
+        (Some(then), Some(now)) if then != now => {
+            Some(format!("{} was modified after it was moved", record.new_name))
+        }
+        _ => None,
+    }
+}
+
+/// Perform the change `record` describes again, starting from `source`, where undo put
+/// the file back. Returns where the file ended up, and the kind of the new record.
+fn reapply(
+    record: &MoveRecord,
+    source: &Path,
+    conflict_resolution: &ConflictResolution,
+    verify_hash: bool,
+) -> Result<(PathBuf, RecordKind), String> {
+    let new_path = match &record.kind {
+        RecordKind::Extracted { archive_moved_to, archive_trashed } => {
+            return reextract(source, &record.new_path, archive_moved_to.as_deref(), *archive_trashed, conflict_resolution, verify_hash);
+        }
+        RecordKind::Compacted { entry } => return recompact(source, &record.new_path, entry),
+        _ => reapply_to(record, source, conflict_resolution, verify_hash)?,
+    };
+    Ok((new_path, record.kind.clone()))
+}
+
+fn reapply_to(
+    record: &MoveRecord,
+    source: &Path,
+    conflict_resolution: &ConflictResolution,
+    verify_hash: bool,
+) -> Result<PathBuf, String> {
+    if !source.exists() {
+        return Err("File is no longer where undo restored it".to_string());
+    }
+    
+    let still_identical = |other: &PathBuf| -> Result<(), String> {
+        match files_identical(source, other) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("File is no longer identical to the copy that was kept".to_string()),
+            Err(e) => Err(format!("Failed to compare files: {}", e)),
+        }
+    };
+    
+    match &record.kind {
+        RecordKind::Duplicate { duplicate_of, deleted: true } => {
+            still_identical(duplicate_of)?;
+            fs::remove_file(source)
+                .map_err(|e| format!("Failed to delete duplicate: {}", e))?;
+            Ok(duplicate_of.clone())
+        }
+        RecordKind::DuplicateLinked { target } => {
+            still_identical(target)?;
+            link_duplicate(source, target)?;
+            Ok(target.clone())
+        }
+        RecordKind::Trashed => trash_file(source, verify_hash),
+        _ => {
+            let dest_dir = record.new_path.parent()
+                .ok_or_else(|| "Invalid destination".to_string())?;
+            let file_name = record.new_path.file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| "Invalid destination".to_string())?;
+            let result = match record.kind.mirror_action() {
+                Some(action) => mirror_file(action, source, dest_dir, "", file_name, conflict_resolution, verify_hash),
+                None => move_file(source, dest_dir, "", file_name, conflict_resolution, verify_hash),
+            };
+            if result.success {
+                Ok(result.destination)
+            } else {
+                Err(result.error.unwrap_or_else(|| "Unknown error".to_string()))
+            }
+        }
+    }
+}
+
+/// Pack `source` into `archive` again under the name it had last time, or a numbered one
+/// if that has been taken since.
+fn recompact(source: &Path, archive: &Path, entry: &str) -> Result<(PathBuf, RecordKind), String> {
+    if !source.exists() {
+        return Err("File is no longer where undo restored it".to_string());
+    }
+    let entry = archive_files(archive, &[(source.to_path_buf(), entry.to_string())])?
+        .pop()
+        .ok_or_else(|| "Failed to add file to archive".to_string())?;
+    fs::remove_file(source)
+        .map_err(|e| format!("Archived {:?} but could not delete it: {}", source, e))?;
+    Ok((archive.to_path_buf(), RecordKind::Compacted { entry }))
+}
+
+/// Unpack `archive` into `folder` again and send the archive where it went last time.
+/// The default limits apply, since the ones in use at the time aren't recorded.
+fn reextract(
+    archive: &Path,
+    folder: &Path,
+    archive_moved_to: Option<&Path>,
+    archive_trashed: bool,
+    conflict_resolution: &ConflictResolution,
+    verify_hash: bool,
+) -> Result<(PathBuf, RecordKind), String> {
+    let dest_dir = folder.parent().ok_or_else(|| "Invalid destination".to_string())?;
+    let archive_name = archive.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let folder_name = folder.file_name()
+        .and_then(|n| n.to_str())
+        .or_else(|| archive_stem(archive_name))
+        .ok_or_else(|| "Invalid destination".to_string())?;
+    let extraction = extract_archive(archive, dest_dir, folder_name, &ExtractLimits::default())?;
+    
+    let (after, root) = match archive_moved_to.and_then(Path::parent) {
+        _ if archive_trashed => (AfterExtract::Trash, dest_dir),
+        Some(parent) => (AfterExtract::MoveTo(String::new()), parent),
+        None => (AfterExtract::Keep, dest_dir),
+    };
+    let archive_moved_to = dispose_archive(archive, &after, root, conflict_resolution, verify_hash)
+        .unwrap_or_else(|e| {
+            log::warn!("Extracted {:?} again but could not move it: {}", archive, e);
+            None
+        });
+    let kind = RecordKind::Extracted {
+        archive_trashed: archive_trashed && archive_moved_to.is_some(),
+        archive_moved_to,
+    };
+    Ok((extraction.folder, kind))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryStats {
     pub total: usize,
     pub today: usize,
     pub this_week: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_extraction_keeps_added_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        let folder = root.join("data");
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(folder.join("a.txt"), b"a").unwrap();
+        let extracted_at = filetime::FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(folder.join("a.txt"), extracted_at).unwrap();
+
+        let history = History::at(root.join("history.json"), 10);
+        history.add(MoveRecord {
+            id: "extracted".to_string(),
+            original_path: root.join("data.zip"),
+            new_path: folder.clone(),
+            rule_name: "Unpack".to_string(),
+            timestamp: DateTime::from_timestamp(1_600_000_100, 0).unwrap(),
+            file_size: 1,
+            can_undo: true,
+            original_name: "data.zip".to_string(),
+            new_name: "data".to_string(),
+            kind: RecordKind::Extracted { archive_moved_to: None, archive_trashed: false },
+            batch_id: None,
+            modified: None,
+            profile_id: None,
+        });
+
+        fs::write(folder.join("notes.txt"), b"mine").unwrap();
+        let error = history.undo("extracted", &UndoOptions::default()).unwrap_err();
+        assert!(error.contains("notes.txt"), "{}", error);
+        assert!(folder.join("notes.txt").exists());
+
+        let forced = UndoOptions { force: true, ..Default::default() };
+        history.undo("extracted", &forced).unwrap();
+        assert!(!folder.exists());
+    }
+}
@@ -0,0 +1,312 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::engine::hash::files_identical;
+
+/// One line of the journal file. A `Begin` without a matching `Done` is a move that was
+/// interrupted and has to be finished or rolled back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum JournalLine {
+    Begin {
+        id: String,
+        source: PathBuf,
+        destination: PathBuf,
+        /// Where a cross-device copy is written before being renamed into place
+        #[serde(default)]
+        temp: Option<PathBuf>,
+    },
+    Done {
+        id: String,
+    },
+}
+
+/// A move that has been announced in the journal. Pass it to `finish` once the move has
+/// either happened or failed cleanly.
+pub struct JournalEntry {
+    id: String,
+    /// False when writing the `Begin` line failed, so there is nothing to finish
+    journaled: bool,
+}
+
+/// The journal file is shared with any other AutoSort process, so every write and the
+/// check before truncating it happen under a lock on the file itself.
+struct Journal {
+    /// `None` for the journal in the config folder
+    path: Option<PathBuf>,
+    file: Option<File>,
+    /// Moves this process has begun but not finished
+    open: usize,
+    /// Whether this process holds the move lock, which makes it the one to clear the journal
+    owns_lock: bool,
+}
+
+/// Held by the one process that recovered the journal, for as long as it runs. Another
+/// process, like a second `autosort-cli` command, can't take it and so leaves the journal
+/// of moves that may still be in progress alone. The OS releases it if the process dies.
+pub struct MoveLock {
+    _file: File,
+}
+
+static JOURNAL: Mutex<Journal> = Mutex::new(Journal { path: None, file: None, open: 0, owns_lock: false });
+
+fn get_journal_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("autosort");
+    fs::create_dir_all(&config_dir).ok();
+    config_dir.join("journal.jsonl")
+}
+
+fn get_lock_path() -> PathBuf {
+    get_journal_path().with_file_name("journal.lock")
+}
+
+impl Journal {
+    fn file(&mut self) -> Result<&File, String> {
+        if self.file.is_none() {
+            let path = self.path.clone().unwrap_or_else(get_journal_path);
+            let file = OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open move journal: {}", e))?;
+            self.file = Some(file);
+        }
+        Ok(self.file.as_ref().expect("journal file was just opened"))
+    }
+    
+    fn append(&mut self, line: &JournalLine) -> Result<(), String> {
+        let mut content = serde_json::to_string(line)
+            .map_err(|e| format!("Failed to serialize journal entry: {}", e))?;
+        content.push('\n');
+        
+        let mut file = self.file()?;
+        file.lock().map_err(|e| format!("Failed to lock move journal: {}", e))?;
+        let written = file.write_all(content.as_bytes()).and_then(|_| file.sync_data());
+        file.unlock().ok();
+        written.map_err(|e| format!("Failed to write move journal: {}", e))
+    }
+    
+    fn begin(&mut self, source: &Path, destination: &Path, temp: &Path) -> JournalEntry {
+        let id = uuid::Uuid::new_v4().to_string();
+        let line = JournalLine::Begin {
+            id: id.clone(),
+            source: source.to_path_buf(),
+            destination: destination.to_path_buf(),
+            temp: Some(temp.to_path_buf()),
+        };
+        match self.append(&line) {
+            Ok(()) => {
+                self.open += 1;
+                JournalEntry { id, journaled: true }
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                JournalEntry { id, journaled: false }
+            }
+        }
+    }
+    
+    fn finish(&mut self, entry: JournalEntry) {
+        if !entry.journaled {
+            return;
+        }
+        if let Err(e) = self.append(&JournalLine::Done { id: entry.id }) {
+            log::error!("{}", e);
+            return;
+        }
+        self.open = self.open.saturating_sub(1);
+        if self.open == 0 && self.owns_lock {
+            if let Err(e) = self.clear_if_idle() {
+                log::warn!("Failed to truncate move journal: {}", e);
+            }
+        }
+    }
+    
+    /// Empty the journal unless another process still has a move in it.
+    fn clear_if_idle(&mut self) -> Result<(), String> {
+        let mut file = self.file()?;
+        file.lock().map_err(|e| e.to_string())?;
+        let mut content = String::new();
+        let cleared = file.seek(SeekFrom::Start(0))
+            .and_then(|_| file.read_to_string(&mut content))
+            .and_then(|_| if unfinished(&content).is_empty() { file.set_len(0) } else { Ok(()) });
+        file.unlock().ok();
+        cleared.map_err(|e| e.to_string())
+    }
+}
+
+/// Record the intent to move `source` to `destination`, possibly by way of a copy at
+/// `temp`. Journal failures are logged but don't stop the move; the journal only helps
+/// recovery.
+pub fn begin(source: &Path, destination: &Path, temp: &Path) -> JournalEntry {
+    JOURNAL.lock().begin(source, destination, temp)
+}
+
+pub fn finish(entry: JournalEntry) {
+    JOURNAL.lock().finish(entry)
+}
+
+/// Finish or roll back moves a previous run was in the middle of, then clear the journal.
+/// Call once at startup, before any new moves begin, and keep the returned lock for as
+/// long as the process runs. Returns `None` without touching the journal if another
+/// process holds the lock.
+pub fn recover_moves() -> Option<MoveLock> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(get_lock_path())
+        .map_err(|e| log::error!("Failed to open move lock: {}", e))
+        .ok()?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            log::info!("Another AutoSort process is running; leaving its move journal alone");
+            return None;
+        }
+        Err(TryLockError::Error(e)) => {
+            log::error!("Failed to lock move journal: {}", e);
+            return None;
+        }
+    }
+    // Only informative, for whoever wonders which process holds it
+    file.set_len(0)
+        .and_then(|_| write!(file, "{}", std::process::id()))
+        .ok();
+    
+    recover_journal();
+    JOURNAL.lock().owns_lock = true;
+    Some(MoveLock { _file: file })
+}
+
+/// `Begin` lines in `content` without a matching `Done`.
+fn unfinished(content: &str) -> Vec<JournalLine> {
+    let mut incomplete: Vec<JournalLine> = Vec::new();
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        // A torn last line means we died while announcing a move, before it started
+        let Ok(line) = serde_json::from_str::<JournalLine>(line) else {
+            continue;
+        };
+        match line {
+            JournalLine::Begin { .. } => incomplete.push(line),
+            JournalLine::Done { id } => {
+                incomplete.retain(|l| !matches!(l, JournalLine::Begin { id: begun, .. } if *begun == id));
+            }
+        }
+    }
+    incomplete
+}
+
+fn recover_journal() {
+    let path = get_journal_path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return,
+    };
+    
+    for line in unfinished(&content) {
+        if let JournalLine::Begin { source, destination, temp, .. } = line {
+            if let Some(temp) = temp.filter(|t| t.exists()) {
+                match fs::remove_file(&temp) {
+                    Ok(()) => log::warn!("Removed partial copy {:?} of {:?}", temp, source),
+                    Err(e) => log::error!("Failed to remove partial copy {:?}: {}", temp, e),
+                }
+            }
+            recover_move(&source, &destination);
+        }
+    }
+    
+    if let Err(e) = fs::write(&path, "") {
+        log::error!("Failed to clear move journal: {}", e);
+    }
+}
+
+fn recover_move(source: &Path, destination: &Path) {
+    match (source.exists(), destination.exists()) {
+        (true, true) => {
+            if files_identical(source, destination).unwrap_or(false) {
+                // The copy finished but the source was never deleted
+                match fs::remove_file(source) {
+                    Ok(()) => log::warn!("Finished interrupted move of {:?} to {:?}", source, destination),
+                    Err(e) => log::error!("Failed to finish interrupted move of {:?}: {}", source, e),
+                }
+            } else {
+                // Copies are renamed into place only once complete, so the destination is
+                // either a file that was there before or the moved file, and the source
+                // a new file that arrived since. Both are kept.
+                log::warn!("Interrupted move of {:?} left {:?} in place", source, destination);
+            }
+        }
+        (true, false) => log::info!("Interrupted move of {:?} never started", source),
+        (false, true) => log::info!("Interrupted move of {:?} to {:?} had completed", source, destination),
+        (false, false) => log::error!("Interrupted move lost track of {:?} and {:?}", source, destination),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recover_interrupted_copies() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+
+        // Copy finished, source not yet deleted
+        fs::write(root.join("done.pdf"), b"contents").unwrap();
+        fs::write(root.join("done-dest.pdf"), b"contents").unwrap();
+        recover_move(&root.join("done.pdf"), &root.join("done-dest.pdf"));
+        assert!(!root.join("done.pdf").exists());
+        assert!(root.join("done-dest.pdf").exists());
+
+        // Moved, then a different file with the same name arrived at the source
+        fs::write(root.join("report.pdf"), b"new download").unwrap();
+        fs::write(root.join("report-dest.pdf"), b"contents").unwrap();
+        recover_move(&root.join("report.pdf"), &root.join("report-dest.pdf"));
+        assert!(root.join("report.pdf").exists());
+        assert!(root.join("report-dest.pdf").exists());
+
+        // Never touch a file that was there before the move
+        fs::write(root.join("other.pdf"), b"contents").unwrap();
+        fs::write(root.join("existing.pdf"), b"older file").unwrap();
+        recover_move(&root.join("other.pdf"), &root.join("existing.pdf"));
+        assert!(root.join("other.pdf").exists());
+        assert!(root.join("existing.pdf").exists());
+    }
+
+    #[test]
+    fn test_interleaved_writers_keep_each_others_moves() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("journal.jsonl");
+        let journal = |owns_lock| Journal { path: Some(path.clone()), file: None, open: 0, owns_lock };
+        // The app, holding the move lock, and a CLI scan running alongside
+        let (mut app, mut cli) = (journal(true), journal(false));
+        let (source, destination) = (temp.path().join("a.pdf"), temp.path().join("b.pdf"));
+        let pending = |content: String| unfinished(&content).len();
+
+        let from_app = app.begin(&source, &destination, &destination);
+        let from_cli = cli.begin(&source, &destination, &destination);
+        app.finish(from_app);
+        assert_eq!(pending(fs::read_to_string(&path).unwrap()), 1);
+
+        cli.finish(from_cli);
+        assert_eq!(pending(fs::read_to_string(&path).unwrap()), 0);
+        assert!(!fs::read_to_string(&path).unwrap().is_empty());
+
+        // Nothing left in flight, so the app's next finished move clears the file
+        let from_app = app.begin(&source, &destination, &destination);
+        app.finish(from_app);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+
+        // A move whose `Begin` was never written doesn't write a `Done` either
+        cli.finish(JournalEntry { id: "lost".to_string(), journaled: false });
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        assert_eq!(cli.open, 0);
+    }
+}
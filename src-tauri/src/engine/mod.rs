@@ -1,7 +1,25 @@
 pub mod rules;
 pub mod mover;
+pub mod compact;
+pub mod duplicates;
+pub mod extract;
+pub mod hash;
 pub mod history;
+pub mod journal;
+pub mod planner;
+pub mod rename;
+pub mod template;
+pub mod trash;
 
 pub use rules::*;
 pub use mover::*;
+pub use compact::*;
+pub use duplicates::*;
+pub use extract::*;
+pub use hash::*;
 pub use history::*;
+pub use journal::{recover_moves, MoveLock};
+pub use planner::*;
+pub use rename::*;
+pub use template::*;
+pub use trash::*;
@@ -1,106 +1,604 @@
 use std::fs;
 use std::path::{Path, PathBuf};
-use crate::config::schema::ConflictResolution;
+use crate::config::schema::{ConflictResolution, RuleAction, UndoConflict};
+use crate::engine::hash::files_identical;
+use crate::engine::journal;
+use crate::engine::trash::trash_file;
 
 pub struct MoveResult {
     pub source: PathBuf,
     pub destination: PathBuf,
     pub success: bool,
+    pub status: MoveStatus,
     pub error: Option<String>,
+    /// Existing file the source turned out to be identical to
+    pub duplicate_of: Option<PathBuf>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveStatus {
+    Moved,
+    Failed,
+    /// The destination is taken and the conflict policy is `Ask`; `destination` is the
+    /// existing file.
+    NeedsDecision,
+    /// The source was identical to the file already at the destination and was dropped.
+    /// `destination` is where it went in the duplicates folder, or the existing file if
+    /// it was deleted.
+    Deduplicated,
+    /// The file was copied to `destination` but the source could not be deleted, so it
+    /// now exists in both places. `error` says why.
+    CopiedSourceRemains,
+    /// The file was copied to `destination` and the original left in place.
+    Copied,
+    /// `destination` was created as a hard link to the file.
+    Hardlinked,
+    /// `destination` was created as a symbolic link to the file.
+    Symlinked,
+    /// An identical copy, or a link to the file, already exists at `destination`, so
+    /// nothing was done.
+    AlreadyMirrored,
+    /// `source` already is the file at `destination`, e.g. when a schedule sweeps its own
+    /// rule's destination folder, so nothing was done.
+    AlreadyInPlace,
+    /// The file was moved to the trash; `destination` is where it is in the trash, or
+    /// the original path if that isn't known.
+    Trashed,
+    /// The file was deleted permanently.
+    Deleted,
+    /// The archive was unpacked into the folder `destination`.
+    Extracted,
+}
+
+/// Do what a rule's `action` says with `source`. The destination arguments are only used
+/// by actions that put the file somewhere.
+pub fn apply_action(
+    action: RuleAction,
+    source: &Path,
+    destination_root: &Path,
+    destination_folder: &str,
+    file_name: &str,
+    conflict_resolution: &ConflictResolution,
+    verify_hash: bool,
+) -> MoveResult {
+    let outcome = match action {
+        RuleAction::Move => {
+            return move_file(source, destination_root, destination_folder, file_name, conflict_resolution, verify_hash);
+        }
+        RuleAction::Copy | RuleAction::Hardlink | RuleAction::Symlink => {
+            return mirror_file(action, source, destination_root, destination_folder, file_name, conflict_resolution, verify_hash);
+        }
+        RuleAction::Trash => trash_file(source, verify_hash).map(|trashed| (trashed, MoveStatus::Trashed)),
+        RuleAction::Delete => fs::remove_file(source)
+            .map(|_| (source.to_path_buf(), MoveStatus::Deleted))
+            .map_err(|e| format!("Failed to delete file: {}", e)),
+        RuleAction::Leave => Err("The rule leaves this file in place".to_string()),
+        // Needs limits and a plan for the archive, see `extract_archive`
+        RuleAction::Extract => Err("Archives are unpacked with extract_archive".to_string()),
+    };
+    
+    match outcome {
+        Ok((destination, status)) => MoveResult {
+            source: source.to_path_buf(),
+            destination,
+            success: true,
+            status,
+            error: None,
+            duplicate_of: None,
+        },
+        Err(error) => MoveResult {
+            source: source.to_path_buf(),
+            destination: source.to_path_buf(),
+            success: false,
+            status: MoveStatus::Failed,
+            error: Some(error),
+            duplicate_of: None,
+        },
+    }
+}
+
+/// Move `source` into `destination_root/destination_folder` under the name `file_name`.
+/// With `verify_hash`, copies between devices are hashed and compared before the source
+/// is deleted; otherwise only their size is checked.
 pub fn move_file(
     source: &Path,
     destination_root: &Path,
     destination_folder: &str,
+    file_name: &str,
     conflict_resolution: &ConflictResolution,
+    verify_hash: bool,
 ) -> MoveResult {
     let dest_dir = destination_root.join(destination_folder);
     
+    // Renaming or deduplicating a file against itself would rename or delete it
+    if is_in_place(source, &dest_dir.join(file_name)) {
+        return MoveResult {
+            source: source.to_path_buf(),
+            destination: source.to_path_buf(),
+            success: false,
+            status: MoveStatus::AlreadyInPlace,
+            error: Some("The file is already in its destination".to_string()),
+            duplicate_of: None,
+        };
+    }
+    
     // Create destination directory if it doesn't exist
     if let Err(e) = fs::create_dir_all(&dest_dir) {
         return MoveResult {
             source: source.to_path_buf(),
             destination: dest_dir,
             success: false,
+            status: MoveStatus::Failed,
             error: Some(format!("Failed to create directory: {}", e)),
+            duplicate_of: None,
         };
     }
     
-    let file_name = source.file_name().unwrap_or_default();
-    let mut dest_path = dest_dir.join(file_name);
-    
-    // Handle conflicts
-    if dest_path.exists() {
-        match conflict_resolution {
-            ConflictResolution::Skip => {
-                return MoveResult {
-                    source: source.to_path_buf(),
-                    destination: dest_path,
-                    success: false,
-                    error: Some("File already exists, skipped".to_string()),
-                };
-            }
-            ConflictResolution::Rename => {
-                dest_path = get_unique_path(&dest_path);
-            }
-            ConflictResolution::Overwrite => {
-                // Will overwrite
-            }
-            ConflictResolution::Ask => {
-                // For now, default to rename
-                dest_path = get_unique_path(&dest_path);
+    if let ConflictResolution::Deduplicate { duplicates_folder } = conflict_resolution {
+        let existing = dest_dir.join(file_name);
+        if existing.exists() {
+            match files_identical(source, &existing) {
+                Ok(true) => {
+                    let folder = duplicates_folder.as_ref().map(|f| destination_root.join(f));
+                    return drop_duplicate(source, &existing, folder.as_deref(), verify_hash);
+                }
+                Ok(false) => {}
+                // Not knowing is treated as "different": renaming never loses data
+                Err(e) => log::warn!("Failed to compare {:?} with {:?}: {}", source, existing, e),
             }
         }
     }
     
-    // Perform the move
-    match fs::rename(source, &dest_path) {
-        Ok(_) => MoveResult {
+    let dest_path = match resolve_destination(&dest_dir.join(file_name), conflict_resolution, |p| p.exists()) {
+        Ok(path) => path,
+        Err((path, error)) => {
+            let status = if *conflict_resolution == ConflictResolution::Ask {
+                MoveStatus::NeedsDecision
+            } else {
+                MoveStatus::Failed
+            };
+            return MoveResult {
+                source: source.to_path_buf(),
+                destination: path,
+                success: false,
+                status,
+                error: Some(error),
+                duplicate_of: None,
+            };
+        }
+    };
+    
+    match rename_or_copy(source, &dest_path, verify_hash) {
+        Ok(Transfer::Moved) => MoveResult {
             source: source.to_path_buf(),
             destination: dest_path,
             success: true,
+            status: MoveStatus::Moved,
             error: None,
+            duplicate_of: None,
         },
-        Err(e) => {
-            // Try copy + delete if rename fails (cross-device move)
-            match fs::copy(source, &dest_path) {
-                Ok(_) => {
-                    if let Err(del_err) = fs::remove_file(source) {
-                        log::warn!("Failed to delete source after copy: {}", del_err);
-                    }
-                    MoveResult {
-                        source: source.to_path_buf(),
-                        destination: dest_path,
-                        success: true,
-                        error: None,
-                    }
-                }
-                Err(copy_err) => MoveResult {
-                    source: source.to_path_buf(),
-                    destination: dest_path,
-                    success: false,
-                    error: Some(format!("Move failed: {}, Copy failed: {}", e, copy_err)),
-                },
+        Ok(Transfer::SourceRemains(error)) => MoveResult {
+            source: source.to_path_buf(),
+            destination: dest_path,
+            success: true,
+            status: MoveStatus::CopiedSourceRemains,
+            error: Some(error),
+            duplicate_of: None,
+        },
+        Err(error) => MoveResult {
+            source: source.to_path_buf(),
+            destination: dest_path,
+            success: false,
+            status: MoveStatus::Failed,
+            error: Some(error),
+            duplicate_of: None,
+        },
+    }
+}
+
+/// Copy or link `source` into `destination_root/destination_folder` under the name
+/// `file_name`, leaving the original where it is. `action` must be `Copy`, `Hardlink` or
+/// `Symlink`. The copy or link is made under a temporary name first so a half-written
+/// file never shows up at the destination.
+pub fn mirror_file(
+    action: RuleAction,
+    source: &Path,
+    destination_root: &Path,
+    destination_folder: &str,
+    file_name: &str,
+    conflict_resolution: &ConflictResolution,
+    verify_hash: bool,
+) -> MoveResult {
+    let dest_dir = destination_root.join(destination_folder);
+    let mut result = MoveResult {
+        source: source.to_path_buf(),
+        destination: dest_dir.join(file_name),
+        success: false,
+        status: MoveStatus::Failed,
+        error: None,
+        duplicate_of: None,
+    };
+    
+    if is_in_place(source, &result.destination) {
+        result.status = MoveStatus::AlreadyInPlace;
+        result.error = Some("The file is already in its destination".to_string());
+        return result;
+    }
+    
+    if let Err(e) = fs::create_dir_all(&dest_dir) {
+        result.error = Some(format!("Failed to create directory: {}", e));
+        return result;
+    }
+    
+    // The original stays behind, so without this every scan would copy it again
+    if already_mirrored(action, source, &result.destination) {
+        result.status = MoveStatus::AlreadyMirrored;
+        result.error = Some("An identical copy or link already exists".to_string());
+        return result;
+    }
+    
+    result.destination = match resolve_destination(&result.destination, conflict_resolution, |p| p.exists()) {
+        Ok(path) => path,
+        Err((path, error)) => {
+            if *conflict_resolution == ConflictResolution::Ask {
+                result.status = MoveStatus::NeedsDecision;
             }
+            result.destination = path;
+            result.error = Some(error);
+            return result;
+        }
+    };
+    
+    let temp = sibling_temp_path(&result.destination);
+    let (made, status) = match action {
+        RuleAction::Hardlink => (
+            fs::hard_link(source, &temp).map_err(|e| format!("Hard link failed: {}", e)),
+            MoveStatus::Hardlinked,
+        ),
+        RuleAction::Symlink => (
+            symlink(source, &temp).map_err(|e| format!("Symbolic link failed: {}", e)),
+            MoveStatus::Symlinked,
+        ),
+        _ => (
+            copy_verified(source, &temp, verify_hash).map_err(|e| format!("Copy failed: {}", e)),
+            MoveStatus::Copied,
+        ),
+    };
+    match made.and_then(|_| fs::rename(&temp, &result.destination).map_err(|e| e.to_string())) {
+        Ok(()) => {
+            result.success = true;
+            result.status = status;
         }
+        Err(e) => {
+            fs::remove_file(&temp).ok();
+            result.error = Some(e);
+        }
+    }
+    result
+}
+
+/// Whether `source` and `target` name the same file. Parent folders are compared after
+/// resolving them, the file names as they are, so a link at `target` doesn't count.
+pub(crate) fn is_in_place(source: &Path, target: &Path) -> bool {
+    if source == target {
+        return true;
+    }
+    let resolved = |p: &Path| Some((fs::canonicalize(p.parent()?).ok()?, p.file_name()?.to_owned()));
+    source.file_name() == target.file_name()
+        && resolved(source).is_some_and(|s| resolved(target) == Some(s))
+}
+
+/// Whether `destination` already is what mirroring `source` with `action` would make.
+pub(crate) fn already_mirrored(action: RuleAction, source: &Path, destination: &Path) -> bool {
+    match action {
+        RuleAction::Symlink => fs::read_link(destination).is_ok_and(|target| target == source),
+        RuleAction::Hardlink => is_same_file(source, destination),
+        _ => destination.is_file() && files_identical(source, destination).unwrap_or(false),
+    }
+}
+
+/// Undo `mirror_file`: remove the copy or link at `mirror`. Refuses when it has been
+/// replaced by something else in the meantime, since that may not exist anywhere else.
+pub fn remove_mirror(mirror: &Path, original: &Path, action: RuleAction) -> Result<(), String> {
+    let Ok(meta) = fs::symlink_metadata(mirror) else {
+        // Already gone
+        return Ok(());
+    };
+    
+    match action {
+        RuleAction::Symlink if !meta.file_type().is_symlink() => {
+            return Err(format!("{:?} is no longer a link", mirror));
+        }
+        RuleAction::Hardlink if original.exists() && !is_same_file(original, mirror) => {
+            return Err(format!("{:?} is no longer linked to the original", mirror));
+        }
+        _ => {}
+    }
+    fs::remove_file(mirror).map_err(|e| format!("Failed to remove {:?}: {}", mirror, e))
+}
+
+/// Whether `a` and `b` are hard links to the same file.
+#[cfg(unix)]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
     }
 }
 
-pub fn undo_move(source: &Path, original_location: &Path) -> Result<(), String> {
+/// Without file ids to compare, identical contents are the closest we can check.
+#[cfg(not(unix))]
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    files_identical(a, b).unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Needs Developer Mode or administrator rights on Windows.
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// How a file got to its destination.
+pub(crate) enum Transfer {
+    Moved,
+    /// Copied into place, but deleting the source failed for the given reason
+    SourceRemains(String),
+}
+
+/// Rename `source` to `destination`, falling back to a verified copy when a plain rename
+/// isn't possible (e.g. across devices). The move is journaled so a crash halfway
+/// through a copy can be cleaned up on the next start.
+pub(crate) fn rename_or_copy(source: &Path, destination: &Path, verify_hash: bool) -> Result<Transfer, String> {
+    let temp = sibling_temp_path(destination);
+    let entry = journal::begin(source, destination, &temp);
+    let result = rename_or_copy_unjournaled(source, destination, &temp, verify_hash);
+    journal::finish(entry);
+    result
+}
+
+fn rename_or_copy_unjournaled(
+    source: &Path,
+    destination: &Path,
+    temp: &Path,
+    verify_hash: bool,
+) -> Result<Transfer, String> {
+    let rename_err = match fs::rename(source, destination) {
+        Ok(()) => return Ok(Transfer::Moved),
+        Err(e) => e,
+    };
+    
+    // Copy under a temporary name so a half-written file never shows up at the destination
+    if let Err(copy_err) = copy_verified(source, temp, verify_hash) {
+        fs::remove_file(temp).ok();
+        return Err(format!("Move failed: {}, Copy failed: {}", rename_err, copy_err));
+    }
+    if let Err(e) = fs::rename(temp, destination) {
+        fs::remove_file(temp).ok();
+        return Err(format!("Move failed: {}, Copy failed: {}", rename_err, e));
+    }
+    
+    match fs::remove_file(source) {
+        Ok(()) => Ok(Transfer::Moved),
+        Err(e) => {
+            log::warn!("Failed to delete source after copy: {}", e);
+            Ok(Transfer::SourceRemains(format!("Copied, but the source could not be deleted: {}", e)))
+        }
+    }
+}
+
+/// Copy `source` to `target` with its permissions and timestamps, flush it to disk and
+/// check it matches the source.
+fn copy_verified(source: &Path, target: &Path, verify_hash: bool) -> Result<(), String> {
+    let meta = source.metadata().map_err(|e| e.to_string())?;
+    
+    // `fs::copy` also copies permissions
+    fs::copy(source, target).map_err(|e| e.to_string())?;
+    
+    let atime = filetime::FileTime::from_last_access_time(&meta);
+    let mtime = filetime::FileTime::from_last_modification_time(&meta);
+    if let Err(e) = filetime::set_file_times(target, atime, mtime) {
+        log::warn!("Failed to preserve timestamps of {:?}: {}", source, e);
+    }
+    
+    fs::File::open(target)
+        .and_then(|f| f.sync_all())
+        .map_err(|e| format!("Failed to flush copy: {}", e))?;
+    
+    let copied = target.metadata().map_err(|e| e.to_string())?.len();
+    if copied != meta.len() {
+        return Err(format!("Copy is {} bytes, expected {}", copied, meta.len()));
+    }
+    if verify_hash && !files_identical(source, target).map_err(|e| e.to_string())? {
+        return Err("Copy does not match the source".to_string());
+    }
+    Ok(())
+}
+
+/// `source` has the same contents as `existing`: delete it, or move it into
+/// `duplicates_folder` if one is configured.
+fn drop_duplicate(
+    source: &Path,
+    existing: &Path,
+    duplicates_folder: Option<&Path>,
+    verify_hash: bool,
+) -> MoveResult {
+    let outcome = match duplicates_folder {
+        Some(folder) => fs::create_dir_all(folder)
+            .map_err(|e| format!("Failed to create directory: {}", e))
+            .and_then(|_| {
+                let target = get_unique_path(
+                    &folder.join(source.file_name().unwrap_or_default()),
+                    |p| p.exists(),
+                );
+                rename_or_copy(source, &target, verify_hash).map(|transfer| (target, transfer))
+            }),
+        None => fs::remove_file(source)
+            .map(|_| (existing.to_path_buf(), Transfer::Moved))
+            .map_err(|e| format!("Failed to delete duplicate: {}", e)),
+    };
+    
+    match outcome {
+        Ok((destination, transfer)) => MoveResult {
+            source: source.to_path_buf(),
+            destination,
+            success: true,
+            status: match transfer {
+                Transfer::Moved => MoveStatus::Deduplicated,
+                Transfer::SourceRemains(_) => MoveStatus::CopiedSourceRemains,
+            },
+            error: match transfer {
+                Transfer::Moved => None,
+                Transfer::SourceRemains(error) => Some(error),
+            },
+            duplicate_of: Some(existing.to_path_buf()),
+        },
+        Err(error) => MoveResult {
+            source: source.to_path_buf(),
+            destination: existing.to_path_buf(),
+            success: false,
+            status: MoveStatus::Failed,
+            error: Some(error),
+            duplicate_of: Some(existing.to_path_buf()),
+        },
+    }
+}
+
+/// Move `source` back to `original_location`, recreating missing folders and handling
+/// an occupied location with `conflict`. Returns where the file was restored to.
+pub fn undo_move(
+    source: &Path,
+    original_location: &Path,
+    conflict: &UndoConflict,
+    verify_hash: bool,
+) -> Result<PathBuf, String> {
     if !source.exists() {
         return Err("Source file no longer exists".to_string());
     }
     
-    if original_location.exists() {
-        return Err("Original location already has a file".to_string());
+    let target = restore_target(original_location, conflict)?;
+    match rename_or_copy(source, &target, verify_hash) {
+        Ok(Transfer::Moved) => Ok(target),
+        Ok(Transfer::SourceRemains(error)) => {
+            log::warn!("Restored {:?} but the sorted copy remains: {}", target, error);
+            Ok(target)
+        }
+        Err(e) => Err(format!("Failed to restore file: {}", e)),
+    }
+}
+
+/// Where to restore a file that came from `original_location`, with its parent folder
+/// created if it was deleted in the meantime.
+pub(crate) fn restore_target(original_location: &Path, conflict: &UndoConflict) -> Result<PathBuf, String> {
+    let target = match conflict {
+        _ if !original_location.exists() => original_location.to_path_buf(),
+        UndoConflict::Fail => return Err("Original location already has a file".to_string()),
+        UndoConflict::Rename => get_unique_path(original_location, |p| p.exists()),
+        UndoConflict::RestoreTo(folder) => {
+            let target = folder.join(original_location.file_name().unwrap_or_default());
+            if target.exists() {
+                get_unique_path(&target, |p| p.exists())
+            } else {
+                target
+            }
+        }
+    };
+    
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to recreate {:?}: {}", parent, e))?;
+    }
+    Ok(target)
+}
+
+/// Undo a deduplicated move whose source was deleted, by copying the identical file that
+/// was kept back to the original location.
+pub fn restore_duplicate(
+    kept: &Path,
+    original_location: &Path,
+    conflict: &UndoConflict,
+    verify_hash: bool,
+) -> Result<PathBuf, String> {
+    if !kept.exists() {
+        return Err("Duplicate file no longer exists".to_string());
+    }
+    
+    let target = restore_target(original_location, conflict)?;
+    let temp = sibling_temp_path(&target);
+    copy_verified(kept, &temp, verify_hash)
+        .and_then(|_| fs::rename(&temp, &target).map_err(|e| e.to_string()))
+        .map_err(|e| {
+            fs::remove_file(&temp).ok();
+            format!("Failed to restore file: {}", e)
+        })?;
+    Ok(target)
+}
+
+/// Apply the conflict policy to a destination path. `is_taken` decides whether a path is
+/// already occupied, so callers can account for moves that have only been planned.
+pub fn resolve_destination(
+    dest_path: &Path,
+    conflict_resolution: &ConflictResolution,
+    is_taken: impl Fn(&Path) -> bool,
+) -> Result<PathBuf, (PathBuf, String)> {
+    if !is_taken(dest_path) {
+        return Ok(dest_path.to_path_buf());
+    }
+    
+    match conflict_resolution {
+        ConflictResolution::Skip => {
+            Err((dest_path.to_path_buf(), "File already exists, skipped".to_string()))
+        }
+        // Identical files are handled before this point, so what is left is a real conflict
+        ConflictResolution::Rename | ConflictResolution::Deduplicate { .. } => {
+            Ok(get_unique_path(dest_path, is_taken))
+        }
+        ConflictResolution::Overwrite => Ok(dest_path.to_path_buf()),
+        ConflictResolution::Ask => {
+            Err((dest_path.to_path_buf(), "File already exists, waiting for a decision".to_string()))
+        }
+    }
+}
+
+/// Replace `duplicate` with a hard link to the identical file `keep`. The link is created
+/// next to the duplicate first and renamed over it, so the file never goes missing.
+pub fn link_duplicate(duplicate: &Path, keep: &Path) -> Result<(), String> {
+    let temp = sibling_temp_path(duplicate);
+    fs::hard_link(keep, &temp)
+        .map_err(|e| format!("Failed to create hard link: {}", e))?;
+    fs::rename(&temp, duplicate).map_err(|e| {
+        fs::remove_file(&temp).ok();
+        format!("Failed to replace duplicate: {}", e)
+    })
+}
+
+/// Undo `link_duplicate`: give `linked` its own copy of `target`'s contents again.
+pub fn unlink_duplicate(linked: &Path, target: &Path) -> Result<(), String> {
+    if !target.exists() {
+        return Err("Linked file no longer exists".to_string());
     }
     
-    fs::rename(source, original_location)
-        .map_err(|e| format!("Failed to restore file: {}", e))
+    let temp = sibling_temp_path(linked);
+    fs::copy(target, &temp)
+        .map_err(|e| format!("Failed to restore file: {}", e))?;
+    fs::rename(&temp, linked).map_err(|e| {
+        fs::remove_file(&temp).ok();
+        format!("Failed to restore file: {}", e)
+    })
+}
+
+pub(crate) fn sibling_temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}.autosort-tmp", name, uuid::Uuid::new_v4()))
 }
 
-fn get_unique_path(path: &Path) -> PathBuf {
+pub(crate) fn get_unique_path(path: &Path, is_taken: impl Fn(&Path) -> bool) -> PathBuf {
     let parent = path.parent().unwrap_or(Path::new("."));
     let stem = path.file_stem().unwrap_or_default().to_str().unwrap_or("");
     let extension = path.extension().map(|e| e.to_str().unwrap_or("")).unwrap_or("");
@@ -114,7 +612,7 @@ fn get_unique_path(path: &Path) -> PathBuf {
         };
         
         let new_path = parent.join(new_name);
-        if !new_path.exists() {
+        if !is_taken(&new_path) {
             return new_path;
         }
         counter += 1;
@@ -125,3 +623,72 @@ fn get_unique_path(path: &Path) -> PathBuf {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deduplicate_drops_identical_and_renames_different() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        fs::create_dir_all(root.join("in")).unwrap();
+        fs::create_dir_all(root.join("Documents")).unwrap();
+        fs::write(root.join("Documents/a.pdf"), b"same").unwrap();
+        fs::write(root.join("Documents/b.pdf"), b"old").unwrap();
+        fs::write(root.join("in/a.pdf"), b"same").unwrap();
+        fs::write(root.join("in/b.pdf"), b"new").unwrap();
+
+        let policy = ConflictResolution::Deduplicate { duplicates_folder: None };
+        let same = move_file(&root.join("in/a.pdf"), &root, "Documents", "a.pdf", &policy, false);
+        assert_eq!(same.status, MoveStatus::Deduplicated);
+        assert_eq!(same.duplicate_of, Some(root.join("Documents/a.pdf")));
+        assert!(!root.join("in/a.pdf").exists());
+
+        let different = move_file(&root.join("in/b.pdf"), &root, "Documents", "b.pdf", &policy, false);
+        assert_eq!(different.status, MoveStatus::Moved);
+        assert_eq!(different.destination, root.join("Documents/b (1).pdf"));
+        assert_eq!(fs::read(root.join("Documents/b.pdf")).unwrap(), b"old");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mirror_links_and_removes_them() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        let source = root.join("movie.mkv");
+        fs::write(&source, b"frames").unwrap();
+
+        let policy = ConflictResolution::Rename;
+        let hard = mirror_file(RuleAction::Hardlink, &source, &root, "Library", "movie.mkv", &policy, false);
+        assert_eq!(hard.status, MoveStatus::Hardlinked);
+        assert!(is_same_file(&source, &hard.destination));
+        let again = mirror_file(RuleAction::Hardlink, &source, &root, "Library", "movie.mkv", &policy, false);
+        assert_eq!(again.status, MoveStatus::AlreadyMirrored);
+
+        let soft = mirror_file(RuleAction::Symlink, &source, &root, "Links", "movie.mkv", &policy, false);
+        assert_eq!(soft.status, MoveStatus::Symlinked);
+        assert_eq!(fs::read_link(&soft.destination).unwrap(), source);
+
+        remove_mirror(&soft.destination, &source, RuleAction::Symlink).unwrap();
+        assert!(remove_mirror(&hard.destination, &source, RuleAction::Symlink).is_err());
+        remove_mirror(&hard.destination, &source, RuleAction::Hardlink).unwrap();
+        assert!(!hard.destination.exists() && fs::symlink_metadata(&soft.destination).is_err());
+        assert_eq!(fs::read(&source).unwrap(), b"frames");
+    }
+
+    #[test]
+    fn test_copy_preserves_timestamps() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        let source = root.join("photo.jpg");
+        fs::write(&source, b"pixels").unwrap();
+        let mtime = filetime::FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(&source, mtime).unwrap();
+
+        copy_verified(&source, &root.join("copy.jpg"), true).unwrap();
+        let copied = root.join("copy.jpg").metadata().unwrap();
+        assert_eq!(filetime::FileTime::from_last_modification_time(&copied), mtime);
+        assert_eq!(copied.len(), 6);
+    }
+}
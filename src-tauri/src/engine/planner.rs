@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use crate::config::schema::{ConflictResolution, RuleAction, SortRule};
+use crate::engine::extract::archive_stem;
+use crate::engine::hash::files_identical;
+use crate::engine::mover::{already_mirrored, is_in_place, resolve_destination};
+use crate::engine::rename::renamed_file_name;
+use crate::engine::rules::match_file_at_depth;
+use crate::engine::template::expand_destination;
+
+/// A move that a scan would perform, worked out without touching the disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedMove {
+    pub source: PathBuf,
+    /// Final path after the conflict policy is applied, or `None` if the move would fail
+    /// or the file would be trashed, deleted, or deleted as a duplicate.
+    pub destination: Option<PathBuf>,
+    pub rule_name: String,
+    #[serde(default)]
+    pub action: RuleAction,
+    pub destination_folder: String,
+    pub error: Option<String>,
+    /// Existing file this one is identical to, when the policy is `Deduplicate`
+    #[serde(default)]
+    pub duplicate_of: Option<PathBuf>,
+}
+
+/// Work out where each file would go. `files` pairs each path with its depth below the
+/// watch folder. Files that no rule matches, that a `Leave` rule keeps in place, or that
+/// are already where their rule would put them are left out.
+///
+/// Destinations claimed by earlier entries in `files` are treated as occupied, so
+/// renames are predicted the same way a real run would produce them.
+pub fn plan_moves(
+    files: &[(PathBuf, usize)],
+    rules: &[SortRule],
+    destination_root: &Path,
+    conflict_resolution: &ConflictResolution,
+) -> Vec<PlannedMove> {
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+    let mut plan = Vec::new();
+
+    for (source, depth) in files {
+        let rule = match match_file_at_depth(source, *depth, rules) {
+            Some(rule) => rule,
+            None => continue,
+        };
+        if rule.action == RuleAction::Leave {
+            continue;
+        }
+
+        let mut planned = PlannedMove {
+            source: source.clone(),
+            destination: None,
+            rule_name: rule.name.clone(),
+            action: rule.action,
+            destination_folder: rule.destination_folder.clone(),
+            error: None,
+            duplicate_of: None,
+        };
+        if !rule.action.uses_destination() {
+            planned.destination_folder = String::new();
+            planned.error = source.metadata().err().map(|e| format!("Cannot read source: {}", e));
+            plan.push(planned);
+            continue;
+        }
+
+        let file_name = match expand_destination(rule, source)
+            .and_then(|folder| Ok((folder, renamed_file_name(rule, source)?)))
+        {
+            Ok((folder, file_name)) => {
+                planned.destination_folder = folder;
+                file_name
+            }
+            Err(error) => {
+                planned.error = Some(error);
+                plan.push(planned);
+                continue;
+            }
+        };
+
+        let dest_dir = destination_root.join(&planned.destination_folder);
+        if rule.action != RuleAction::Extract && is_in_place(source, &dest_dir.join(&file_name)) {
+            continue;
+        }
+        if let Some(error) = predict_error(source, &dest_dir) {
+            planned.error = Some(error);
+            plan.push(planned);
+            continue;
+        }
+
+        let is_taken = |p: &Path| claimed.contains(p) || p.exists();
+        let mut target = dest_dir.join(&file_name);
+        let mut policy = conflict_resolution;
+
+        // Archives are unpacked into a new folder, numbered if the name is taken
+        if rule.action == RuleAction::Extract {
+            match archive_stem(&file_name) {
+                Some(stem) => {
+                    target = dest_dir.join(stem);
+                    policy = &ConflictResolution::Rename;
+                }
+                None => {
+                    planned.error = Some(format!("{} is not a zip or tar archive", file_name));
+                    plan.push(planned);
+                    continue;
+                }
+            }
+        }
+
+        if matches!(rule.action, RuleAction::Copy | RuleAction::Hardlink | RuleAction::Symlink)
+            && already_mirrored(rule.action, source, &target)
+        {
+            planned.error = Some("An identical copy or link already exists".to_string());
+            plan.push(planned);
+            continue;
+        }
+
+        if let (RuleAction::Move, ConflictResolution::Deduplicate { duplicates_folder }) = (rule.action, conflict_resolution) {
+            if target.exists() && files_identical(source, &target).unwrap_or(false) {
+                planned.duplicate_of = Some(target.clone());
+                match duplicates_folder {
+                    Some(folder) => {
+                        target = destination_root.join(folder).join(source.file_name().unwrap_or_default());
+                        policy = &ConflictResolution::Rename;
+                    }
+                    None => {
+                        plan.push(planned);
+                        continue;
+                    }
+                }
+            }
+        }
+
+        match resolve_destination(&target, policy, is_taken) {
+            Ok(path) => {
+                claimed.insert(path.clone());
+                planned.destination = Some(path);
+            }
+            Err((_, error)) => planned.error = Some(error),
+        }
+        plan.push(planned);
+    }
+
+    plan
+}
+
+/// Problems that would make the move fail before any conflict handling happens.
+fn predict_error(source: &Path, dest_dir: &Path) -> Option<String> {
+    if let Err(e) = source.metadata() {
+        return Some(format!("Cannot read source: {}", e));
+    }
+
+    // Find the deepest part of the destination that already exists; that is where
+    // `create_dir_all` would start creating directories.
+    let mut existing = dest_dir;
+    while !existing.exists() {
+        match existing.parent() {
+            Some(parent) => existing = parent,
+            None => return Some("Destination root does not exist".to_string()),
+        }
+    }
+
+    match existing.metadata() {
+        Ok(meta) if !meta.is_dir() => {
+            Some(format!("Failed to create directory: {:?} is a file", existing))
+        }
+        Ok(meta) if meta.permissions().readonly() => {
+            Some(format!("Destination is read-only: {:?}", existing))
+        }
+        Ok(_) => None,
+        Err(e) => Some(format!("Cannot access destination: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::schema::default_rules;
+    use std::fs;
+
+    #[test]
+    fn test_plan_predicts_renames_without_moving() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("b")).unwrap();
+        fs::create_dir_all(root.join("Documents")).unwrap();
+        fs::write(root.join("Documents/report.pdf"), b"old").unwrap();
+        fs::write(root.join("a/report.pdf"), b"a").unwrap();
+        fs::write(root.join("b/report.pdf"), b"b").unwrap();
+
+        let files = vec![(root.join("a/report.pdf"), 1), (root.join("b/report.pdf"), 1)];
+        let plan = plan_moves(&files, &default_rules(), &root, &ConflictResolution::Rename);
+
+        assert_eq!(plan.len(), 2);
+        assert_eq!(plan[0].destination, Some(root.join("Documents/report (1).pdf")));
+        assert_eq!(plan[1].destination, Some(root.join("Documents/report (2).pdf")));
+        assert!(root.join("a/report.pdf").exists());
+        assert!(!root.join("Documents/report (1).pdf").exists());
+    }
+}
@@ -0,0 +1,151 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+use regex::Regex;
+use std::path::Path;
+use crate::config::schema::{RenameTransform, SortRule};
+use crate::engine::rules::FileInfo;
+
+/// Apply a rule's rename transforms, in order, to a file name.
+/// `date` is what `PrefixDate` formats, normally the file's modification time.
+pub fn apply_renames(
+    file_name: &str,
+    transforms: &[RenameTransform],
+    date: DateTime<Local>,
+) -> Result<String, String> {
+    let mut name = file_name.to_string();
+
+    for transform in transforms {
+        name = match transform {
+            RenameTransform::Lowercase => name.to_lowercase(),
+            RenameTransform::Uppercase => name.to_uppercase(),
+            RenameTransform::ReplaceSpaces(replacement) => name.replace(' ', replacement),
+            RenameTransform::StripCopySuffix => strip_copy_suffix(&name),
+            RenameTransform::PrefixDate(format) => format!("{}{}", format_date(date, format)?, name),
+            RenameTransform::RegexReplace { pattern, replacement } => {
+                let re = Regex::new(pattern)
+                    .map_err(|e| format!("Invalid rename pattern \"{}\": {}", pattern, e))?;
+                re.replace_all(&name, replacement.as_str()).into_owned()
+            }
+        };
+    }
+
+    check_file_name(&name)?;
+    Ok(name)
+}
+
+/// The name `path` should have after being sorted by `rule`.
+pub fn renamed_file_name(rule: &SortRule, path: &Path) -> Result<String, String> {
+    let file_name = path.file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Unsupported file name: {:?}", path))?;
+
+    if rule.rename.is_empty() {
+        return Ok(file_name.to_string());
+    }
+
+    let date = FileInfo::from_path(path)
+        .and_then(|info| info.modified)
+        .map(DateTime::from)
+        .unwrap_or_else(Local::now);
+    apply_renames(file_name, &rule.rename, date)
+}
+
+/// Check a rule's transforms before it is saved.
+pub fn validate_renames(transforms: &[RenameTransform]) -> Result<(), String> {
+    for transform in transforms {
+        if let RenameTransform::RegexReplace { pattern, .. } = transform {
+            Regex::new(pattern)
+                .map_err(|e| format!("Invalid rename pattern \"{}\": {}", pattern, e))?;
+        }
+    }
+    // A sample run catches transforms that always produce an unusable name
+    apply_renames("Example File (1).pdf", transforms, Local::now()).map(|_| ())
+}
+
+fn format_date(date: DateTime<Local>, format: &str) -> Result<String, String> {
+    // chrono panics while displaying an invalid format, so check it up front
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("Invalid date format \"{}\"", format));
+    }
+    Ok(date.format(format).to_string())
+}
+
+/// Remove the " (1)" / " - Copy" markers browsers and file managers add to duplicates.
+fn strip_copy_suffix(file_name: &str) -> String {
+    let (stem, extension) = match file_name.rfind('.') {
+        Some(i) if i > 0 => (&file_name[..i], &file_name[i..]),
+        _ => (file_name, ""),
+    };
+
+    let mut stem = stem.trim_end();
+    loop {
+        let trimmed = strip_counter(stem)
+            .or_else(|| stem.strip_suffix(" - Copy"))
+            .map(str::trim_end);
+        match trimmed {
+            Some(t) if !t.is_empty() => stem = t,
+            _ => break,
+        }
+    }
+
+    format!("{}{}", stem, extension)
+}
+
+/// `"name (12)"` -> `Some("name")`
+fn strip_counter(stem: &str) -> Option<&str> {
+    let inner = stem.strip_suffix(')')?;
+    let open = inner.rfind('(')?;
+    let digits = &inner[open + 1..];
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(&inner[..open])
+}
+
+/// Check that `name` is a single file name, so joining it to a folder can't escape it.
+pub(crate) fn check_file_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(format!("Invalid file name: \"{}\"", name));
+    }
+    if name.contains(['/', '\\']) || (cfg!(windows) && name.contains(':')) || name.chars().any(char::is_control) {
+        return Err(format!("File name can't contain path separators: \"{}\"", name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_rename_pipeline() {
+        let date = Local.with_ymd_and_hms(2024, 3, 9, 10, 0, 0).unwrap();
+        let transforms = vec![
+            RenameTransform::StripCopySuffix,
+            RenameTransform::ReplaceSpaces("_".into()),
+            RenameTransform::Lowercase,
+            RenameTransform::PrefixDate("%Y-%m-%d_".into()),
+        ];
+
+        assert_eq!(
+            apply_renames("Quarterly Report (2).PDF", &transforms, date).unwrap(),
+            "2024-03-09_quarterly_report.pdf"
+        );
+        assert_eq!(strip_copy_suffix("photo - Copy (1).jpg"), "photo.jpg");
+        assert_eq!(strip_copy_suffix("(1)"), "(1)");
+    }
+
+    #[test]
+    fn test_rename_rejects_separators() {
+        let transforms = vec![RenameTransform::RegexReplace {
+            pattern: "^".into(),
+            replacement: "../".into(),
+        }];
+        assert!(apply_renames("a.txt", &transforms, Local::now()).is_err());
+        assert!(validate_renames(&transforms).is_err());
+        assert!(check_file_name("../../x").is_err());
+        assert!(check_file_name("/etc/passwd").is_err());
+        assert!(check_file_name("report (2).pdf").is_ok());
+    }
+}
@@ -1,29 +1,121 @@
-use crate::config::schema::{Condition, SortRule};
+use crate::config::schema::{AfterExtract, Condition, SortRule};
+use crate::engine::rename::validate_renames;
+use crate::engine::template::validate_template;
+use chrono::{DateTime, Datelike, Local, Timelike, Utc};
 use regex::Regex;
+use std::cell::OnceCell;
 use std::path::Path;
 
-pub fn match_file<'a>(path: &Path, rules: &'a [SortRule]) -> Option<&'a SortRule> {
-    let file_name = path.file_name()?.to_str()?;
-    let extension = path.extension()?.to_str()?.to_lowercase();
-    let file_size = path.metadata().ok().map(|m| m.len()).unwrap_or(0);
+/// What rule conditions are evaluated against, read once per file.
+pub struct FileInfo<'a> {
+    pub path: &'a Path,
+    pub name: &'a str,
+    /// Lowercased extension, empty if the file has none
+    pub extension: String,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// Birth time where the platform records it
+    pub created: Option<DateTime<Utc>>,
+    /// Sniffed on first use so rules without a `MimeType` condition never read the file
+    mime: OnceCell<Option<&'static str>>,
+}
+
+impl<'a> FileInfo<'a> {
+    pub fn from_path(path: &'a Path) -> Option<Self> {
+        let metadata = path.metadata().ok();
+        
+        Some(Self {
+            path,
+            name: path.file_name()?.to_str()?,
+            extension: path.extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("")
+                .to_lowercase(),
+            size: metadata.as_ref().map(|m| m.len()).unwrap_or(0),
+            modified: metadata.as_ref().and_then(|m| m.modified().ok()).map(DateTime::from),
+            created: metadata.as_ref().and_then(|m| m.created().ok()).map(DateTime::from),
+            mime: OnceCell::new(),
+        })
+    }
     
-    // Sort rules by priority (higher first)
-    let mut sorted_rules: Vec<&SortRule> = rules.iter().filter(|r| r.enabled).collect();
-    sorted_rules.sort_by(|a, b| b.priority.cmp(&a.priority));
+    /// MIME type detected from the file's leading bytes, regardless of its extension.
+    pub fn mime_type(&self) -> Option<&'static str> {
+        *self.mime.get_or_init(|| {
+            infer::get_from_path(self.path)
+                .ok()
+                .flatten()
+                .map(|kind| kind.mime_type())
+        })
+    }
     
-    for rule in sorted_rules {
-        if matches_rule(file_name, &extension, file_size, rule) {
-            return Some(rule);
+    /// When the file arrived: birth time if known, otherwise last modification.
+    fn arrived(&self) -> Option<DateTime<Utc>> {
+        self.created.or(self.modified)
+    }
+}
+
+/// Check a rule before it is saved.
+pub fn validate_rule(rule: &SortRule) -> Result<(), String> {
+    validate_template(&rule.destination_folder)
+        .and_then(|_| match &rule.after_extract {
+            AfterExtract::MoveTo(folder) => validate_template(folder),
+            _ => Ok(()),
+        })
+        .and_then(|_| match &rule.compaction {
+            Some(_) if !rule.action.uses_destination() => {
+                Err("Only rules that put files in a destination folder can compact it".to_string())
+            }
+            Some(compaction) if compaction.archive_folder.contains('{') => {
+                Err("The archive folder can't contain placeholders".to_string())
+            }
+            Some(compaction) => validate_template(&compaction.archive_folder),
+            None => Ok(()),
+        })
+        .and_then(|_| validate_renames(&rule.rename))
+        .and_then(|_| rule.conditions.iter().try_for_each(validate_condition))
+        .map_err(|e| format!("Rule \"{}\": {}", rule.name, e))
+}
+
+fn validate_condition(condition: &Condition) -> Result<(), String> {
+    match condition {
+        Condition::HourBetween { from, to } if *from > 23 || *to > 23 => {
+            Err("Hours must be between 0 and 23".to_string())
+        }
+        Condition::HourBetween { from, to } if from == to => {
+            Err("The hour range is empty; the start and end hour must differ".to_string())
+        }
+        Condition::All(conditions) | Condition::Any(conditions) => {
+            conditions.iter().try_for_each(validate_condition)
         }
+        Condition::Not(condition) => validate_condition(condition),
+        _ => Ok(()),
     }
+}
+
+pub fn match_file<'a>(path: &Path, rules: &'a [SortRule]) -> Option<&'a SortRule> {
+    match_file_at_depth(path, 0, rules)
+}
+
+/// Like `match_file`, for a file `depth` folders below the watch folder.
+/// Rules with a `max_depth` lower than that are skipped.
+pub fn match_file_at_depth<'a>(path: &Path, depth: usize, rules: &'a [SortRule]) -> Option<&'a SortRule> {
+    let info = FileInfo::from_path(path)?;
+    let now = Utc::now();
+    
+    // Sort rules by priority (higher first)
+    let mut sorted_rules: Vec<&SortRule> = rules.iter()
+        .filter(|r| r.enabled && depth <= r.max_depth.unwrap_or(usize::MAX))
+        .collect();
+    sorted_rules.sort_by_key(|r| std::cmp::Reverse(r.priority));
     
-    None
+    sorted_rules.into_iter()
+        .find(|rule| matches_rule(&info, now, rule))
 }
 
-fn matches_rule(file_name: &str, extension: &str, file_size: u64, rule: &SortRule) -> bool {
+fn matches_rule(info: &FileInfo, now: DateTime<Utc>, rule: &SortRule) -> bool {
     // All conditions must match (AND logic)
     for condition in &rule.conditions {
-        if !matches_condition(file_name, extension, file_size, condition) {
+        if !matches_condition(info, now, condition) {
             return false;
         }
     }
@@ -32,21 +124,62 @@ fn matches_rule(file_name: &str, extension: &str, file_size: u64, rule: &SortRul
     !rule.conditions.is_empty()
 }
 
-fn matches_condition(file_name: &str, extension: &str, file_size: u64, condition: &Condition) -> bool {
+fn matches_condition(info: &FileInfo, now: DateTime<Utc>, condition: &Condition) -> bool {
     match condition {
         Condition::Extension(extensions) => {
-            extensions.iter().any(|ext| ext.to_lowercase() == extension)
+            extensions.iter().any(|ext| ext.to_lowercase() == info.extension)
         }
         Condition::NameContains(pattern) => {
-            file_name.to_lowercase().contains(&pattern.to_lowercase())
+            info.name.to_lowercase().contains(&pattern.to_lowercase())
         }
         Condition::NameRegex(pattern) => {
             Regex::new(pattern)
-                .map(|re| re.is_match(file_name))
+                .map(|re| re.is_match(info.name))
                 .unwrap_or(false)
         }
-        Condition::SizeGreaterThan(size) => file_size > *size,
-        Condition::SizeLessThan(size) => file_size < *size,
+        Condition::SizeGreaterThan(size) => info.size > *size,
+        Condition::SizeLessThan(size) => info.size < *size,
+        Condition::OlderThanDays(days) => {
+            info.modified.is_some_and(|m| now - m > chrono::Duration::days(*days as i64))
+        }
+        Condition::NewerThanDays(days) => {
+            info.modified.is_some_and(|m| now - m <= chrono::Duration::days(*days as i64))
+        }
+        Condition::ModifiedBefore(time) => info.modified.is_some_and(|m| m < *time),
+        Condition::ModifiedAfter(time) => info.modified.is_some_and(|m| m > *time),
+        Condition::CreatedBefore(time) => info.created.is_some_and(|c| c < *time),
+        Condition::CreatedAfter(time) => info.created.is_some_and(|c| c > *time),
+        Condition::DayOfWeek(days) => info.arrived()
+            .map(|t| t.with_timezone(&Local).weekday())
+            .is_some_and(|day| days.contains(&day)),
+        Condition::HourBetween { from, to } => info.arrived()
+            .map(|t| t.with_timezone(&Local).hour())
+            .is_some_and(|hour| {
+                if from <= to {
+                    hour >= *from && hour < *to
+                } else {
+                    // Window wraps past midnight, e.g. 22 -> 6
+                    hour >= *from || hour < *to
+                }
+            }),
+        Condition::MimeType(pattern) => info.mime_type()
+            .is_some_and(|mime| mime_matches(pattern, mime)),
+        Condition::All(conditions) => {
+            conditions.iter().all(|c| matches_condition(info, now, c))
+        }
+        Condition::Any(conditions) => {
+            conditions.iter().any(|c| matches_condition(info, now, c))
+        }
+        Condition::Not(condition) => !matches_condition(info, now, condition),
+    }
+}
+
+/// Compare a MIME type against a pattern such as `image/png`, `image/*` or `*/*`.
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    let pattern = pattern.trim().to_lowercase();
+    match pattern.split_once('/') {
+        Some((kind, "*")) => kind == "*" || mime.split('/').next() == Some(kind),
+        _ => pattern == mime,
     }
 }
 
@@ -72,4 +205,97 @@ mod tests {
         assert!(matched.is_some());
         assert_eq!(matched.unwrap().name, "Documents");
     }
+    
+    #[test]
+    fn test_nested_conditions() {
+        let conditions: Vec<Condition> = serde_json::from_str(r#"[
+            {"type": "Any", "value": [
+                {"type": "Extension", "value": ["pdf"]},
+                {"type": "Extension", "value": ["docx"]}
+            ]},
+            {"type": "NameContains", "value": "invoice"},
+            {"type": "Not", "value": {"type": "NameContains", "value": "draft"}}
+        ]"#).unwrap();
+        
+        let mut rule = SortRule::new("Invoices", vec![], "Invoices", 200);
+        rule.conditions = conditions;
+        let rules = vec![rule];
+        
+        assert!(match_file(Path::new("/downloads/invoice-march.pdf"), &rules).is_some());
+        assert!(match_file(Path::new("/downloads/Invoice-april.docx"), &rules).is_some());
+        assert!(match_file(Path::new("/downloads/invoice-draft.pdf"), &rules).is_none());
+        assert!(match_file(Path::new("/downloads/invoice.xlsx"), &rules).is_none());
+    }
+    
+    #[test]
+    fn test_date_conditions() {
+        let now: DateTime<Utc> = "2024-06-15T12:00:00Z".parse().unwrap();
+        let info = FileInfo {
+            path: Path::new("/downloads/old.iso"),
+            name: "old.iso",
+            extension: "iso".to_string(),
+            size: 0,
+            modified: Some("2023-11-01T08:00:00Z".parse().unwrap()),
+            created: None,
+            mime: OnceCell::new(),
+        };
+        
+        let conditions: Vec<Condition> = serde_json::from_str(r#"[
+            {"type": "OlderThanDays", "value": 30},
+            {"type": "ModifiedBefore", "value": "2024-01-01T00:00:00Z"},
+            {"type": "Not", "value": {"type": "NewerThanDays", "value": 30}}
+        ]"#).unwrap();
+        for condition in &conditions {
+            assert!(matches_condition(&info, now, condition), "{:?}", condition);
+        }
+        
+        let weekday = info.modified.unwrap().with_timezone(&Local).weekday();
+        assert!(matches_condition(&info, now, &Condition::DayOfWeek(vec![weekday])));
+        assert!(!matches_condition(&info, now, &Condition::DayOfWeek(vec![weekday.succ()])));
+        assert!(!matches_condition(&info, now, &Condition::CreatedBefore(now)));
+    }
+    
+    #[test]
+    fn test_hour_window_wraps_past_midnight() {
+        use chrono::TimeZone;
+        
+        let at_hour = |hour| FileInfo {
+            path: Path::new("/downloads/late.pdf"),
+            name: "late.pdf",
+            extension: "pdf".to_string(),
+            size: 0,
+            modified: Some(Local.with_ymd_and_hms(2024, 6, 15, hour, 30, 0).unwrap().with_timezone(&Utc)),
+            created: None,
+            mime: OnceCell::new(),
+        };
+        let night = Condition::HourBetween { from: 22, to: 6 };
+        let now = Utc::now();
+        assert!(matches_condition(&at_hour(23), now, &night));
+        assert!(matches_condition(&at_hour(3), now, &night));
+        assert!(!matches_condition(&at_hour(6), now, &night));
+        assert!(!matches_condition(&at_hour(12), now, &night));
+        
+        let mut rule = SortRule::new("Night", vec!["pdf"], "Night", 10);
+        rule.conditions = vec![Condition::Not(Box::new(night))];
+        assert!(validate_rule(&rule).is_ok());
+        rule.conditions = vec![Condition::Not(Box::new(Condition::HourBetween { from: 22, to: 24 }))];
+        assert!(validate_rule(&rule).is_err());
+        rule.conditions = vec![Condition::HourBetween { from: 9, to: 9 }];
+        assert!(validate_rule(&rule).is_err());
+    }
+    
+    #[test]
+    fn test_mime_type_without_extension() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("sniffed");
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        
+        let mut rule = SortRule::new("Sniffed images", vec![], "Images", 10);
+        rule.conditions = vec![Condition::MimeType("image/*".to_string())];
+        let rules = vec![rule];
+        
+        assert_eq!(match_file(&path, &rules).map(|r| r.name.as_str()), Some("Sniffed images"));
+        assert!(mime_matches("IMAGE/PNG", "image/png"));
+        assert!(!mime_matches("video/*", "image/png"));
+    }
 }
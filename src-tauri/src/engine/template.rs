@@ -0,0 +1,235 @@
+use chrono::{DateTime, Local};
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+use crate::config::schema::{Condition, SortRule};
+use crate::engine::rules::FileInfo;
+
+/// Placeholders a destination folder template may use, besides `{regex.N}`.
+const PLACEHOLDERS: &[&str] = &["year", "month", "day", "ext", "rule"];
+
+/// Values substituted into a destination folder template for one file.
+pub struct TemplateContext<'a> {
+    pub rule_name: &'a str,
+    pub extension: &'a str,
+    /// File modification time, used for the date placeholders
+    pub date: DateTime<Local>,
+    /// Capture groups of the rule's `NameRegex` condition; index 0 is the whole match
+    pub captures: Vec<Option<String>>,
+}
+
+enum Token<'t> {
+    Text(&'t str),
+    Placeholder(&'t str),
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return Err(format!("Unmatched '}}' in \"{}\"", template));
+        }
+        let end = rest[start..].find('}')
+            .ok_or_else(|| format!("Unclosed '{{' in \"{}\"", template))? + start;
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        tokens.push(Token::Placeholder(rest[start + 1..end].trim()));
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+
+    Ok(tokens)
+}
+
+fn regex_group(placeholder: &str) -> Option<usize> {
+    placeholder.strip_prefix("regex.")?.parse().ok()
+}
+
+/// Check a destination folder template before a rule is saved: placeholders must be known
+/// and the literal parts must stay inside the destination root.
+pub fn validate_template(template: &str) -> Result<(), String> {
+    for token in tokenize(template)? {
+        if let Token::Placeholder(name) = token {
+            if !PLACEHOLDERS.contains(&name) && regex_group(name).is_none() {
+                return Err(format!("Unknown placeholder {{{}}} in \"{}\"", name, template));
+            }
+        }
+    }
+
+    // Placeholders always expand to a single safe path component, so checking
+    // the template with them blanked out covers the literal parts.
+    let literal: String = tokenize(template)?
+        .into_iter()
+        .map(|t| match t {
+            Token::Text(text) => text,
+            Token::Placeholder(_) => "x",
+        })
+        .collect();
+    check_relative(&literal)
+        .map_err(|e| format!("{} in \"{}\"", e, template))
+}
+
+/// Expand a template into a folder path relative to the destination root.
+pub fn render_template(template: &str, ctx: &TemplateContext) -> Result<String, String> {
+    let mut rendered = String::new();
+
+    for token in tokenize(template)? {
+        match token {
+            Token::Text(text) => rendered.push_str(text),
+            Token::Placeholder(name) => {
+                let value = match name {
+                    "year" => ctx.date.format("%Y").to_string(),
+                    "month" => ctx.date.format("%m").to_string(),
+                    "day" => ctx.date.format("%d").to_string(),
+                    "ext" if ctx.extension.is_empty() => "no-extension".to_string(),
+                    "ext" => ctx.extension.to_string(),
+                    "rule" => ctx.rule_name.to_string(),
+                    _ => {
+                        let group = regex_group(name)
+                            .ok_or_else(|| format!("Unknown placeholder {{{}}}", name))?;
+                        ctx.captures.get(group)
+                            .cloned()
+                            .flatten()
+                            .ok_or_else(|| format!("Regex group {} did not match", group))?
+                    }
+                };
+                rendered.push_str(&sanitize_component(&value)?);
+            }
+        }
+    }
+
+    check_relative(&rendered)?;
+    Ok(rendered)
+}
+
+/// Destination folder for `path` under `rule`, with any placeholders expanded.
+pub fn expand_destination(rule: &SortRule, path: &Path) -> Result<String, String> {
+    expand_folder(&rule.destination_folder, rule, path)
+}
+
+/// Expand another folder template of `rule`, such as where an `Extract` rule puts the
+/// archive, for `path`.
+pub fn expand_folder(template: &str, rule: &SortRule, path: &Path) -> Result<String, String> {
+    if !template.contains('{') {
+        return Ok(template.to_string());
+    }
+
+    let info = FileInfo::from_path(path)
+        .ok_or_else(|| format!("Unsupported file name: {:?}", path))?;
+    let ctx = TemplateContext {
+        rule_name: &rule.name,
+        extension: &info.extension,
+        date: info.modified.map(DateTime::from).unwrap_or_else(Local::now),
+        captures: name_captures(&rule.conditions, info.name).unwrap_or_default(),
+    };
+    render_template(template, &ctx)
+}
+
+/// The part of a template before its first placeholder, i.e. the folder that every
+/// expansion lives under. Empty if the template starts with a placeholder.
+pub fn static_prefix(template: &str) -> PathBuf {
+    let literal = template.split('{').next().unwrap_or("");
+    if literal.len() == template.len() {
+        return PathBuf::from(template);
+    }
+    // Drop the partial component the placeholder belongs to
+    match literal.rfind(['/', '\\']) {
+        Some(i) => PathBuf::from(&literal[..i]),
+        None => PathBuf::new(),
+    }
+}
+
+/// Captures of the first `NameRegex` condition that matches `file_name`. Conditions
+/// under a `Not` are skipped since they only match when the regex doesn't.
+fn name_captures(conditions: &[Condition], file_name: &str) -> Option<Vec<Option<String>>> {
+    conditions.iter().find_map(|condition| match condition {
+        Condition::NameRegex(pattern) => {
+            let caps = Regex::new(pattern).ok()?.captures(file_name)?;
+            Some(caps.iter().map(|m| m.map(|m| m.as_str().to_string())).collect())
+        }
+        Condition::All(nested) | Condition::Any(nested) => name_captures(nested, file_name),
+        _ => None,
+    })
+}
+
+/// Make a substituted value safe to use as (part of) a single path component.
+fn sanitize_component(value: &str) -> Result<String, String> {
+    let value: String = value.chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect();
+    if value.is_empty() || value == "." || value == ".." {
+        return Err(format!("Placeholder expanded to an unsafe folder name: \"{}\"", value));
+    }
+    Ok(value)
+}
+
+fn check_relative(path: &str) -> Result<(), String> {
+    if path.starts_with(['/', '\\']) {
+        return Err("Destination must be relative to the destination root".to_string());
+    }
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir => return Err("Destination may not contain '..'".to_string()),
+            Component::RootDir | Component::Prefix(_) => {
+                return Err("Destination must be relative to the destination root".to_string());
+            }
+        }
+    }
+    // `..` separated by backslashes isn't a component on Unix but is on Windows
+    if path.split(['/', '\\']).any(|part| part == "..") {
+        return Err("Destination may not contain '..'".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn context(captures: Vec<Option<String>>) -> TemplateContext<'static> {
+        TemplateContext {
+            rule_name: "Invoices",
+            extension: "pdf",
+            date: Local.with_ymd_and_hms(2024, 3, 9, 10, 0, 0).unwrap(),
+            captures,
+        }
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let ctx = context(vec![Some("acme-2024.pdf".into()), Some("acme".into())]);
+        assert_eq!(render_template("Images/{year}/{month}", &ctx).unwrap(), "Images/2024/03");
+        assert_eq!(render_template("Code/{ext}", &ctx).unwrap(), "Code/pdf");
+        assert_eq!(render_template("{rule}/{regex.1}", &ctx).unwrap(), "Invoices/acme");
+        assert!(render_template("{regex.2}", &ctx).is_err());
+    }
+
+    #[test]
+    fn test_rejects_unsafe_templates() {
+        assert!(validate_template("Images/{year}").is_ok());
+        assert!(validate_template("../Outside").is_err());
+        assert!(validate_template("/etc").is_err());
+        assert!(validate_template("Docs\\..\\..").is_err());
+        assert!(validate_template("{unknown}").is_err());
+        assert!(validate_template("Docs/{year").is_err());
+
+        let ctx = context(vec![None, Some("..".into())]);
+        assert!(render_template("Invoices/{regex.1}", &ctx).is_err());
+        let ctx = context(vec![None, Some("../../etc".into())]);
+        assert_eq!(render_template("Invoices/{regex.1}", &ctx).unwrap(), "Invoices/.._.._etc");
+    }
+
+    #[test]
+    fn test_static_prefix() {
+        assert_eq!(static_prefix("Images"), PathBuf::from("Images"));
+        assert_eq!(static_prefix("Images/{year}/{month}"), PathBuf::from("Images"));
+        assert_eq!(static_prefix("Code/by-{ext}"), PathBuf::from("Code"));
+        assert_eq!(static_prefix("{ext}"), PathBuf::new());
+    }
+}
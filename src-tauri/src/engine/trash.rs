@@ -0,0 +1,209 @@
+use std::path::{Path, PathBuf};
+use crate::config::schema::UndoConflict;
+
+/// Move `path` to the trash. Returns where the file now is, so it can be restored later;
+/// on platforms where the trash location isn't known this is the original path.
+pub fn trash_file(path: &Path, verify_hash: bool) -> Result<PathBuf, String> {
+    imp::trash_file(path, verify_hash)
+}
+
+/// Put a file that `trash_file` moved to `trashed` back at `original_location`, handling
+/// an occupied location with `conflict`. Returns where the file was restored to.
+pub fn restore_from_trash(
+    trashed: &Path,
+    original_location: &Path,
+    conflict: &UndoConflict,
+    verify_hash: bool,
+) -> Result<PathBuf, String> {
+    imp::restore_from_trash(trashed, original_location, conflict, verify_hash)
+}
+
+/// The home trash of the freedesktop.org Trash specification: the file goes to
+/// `$XDG_DATA_HOME/Trash/files` and a `.trashinfo` file with its original path and
+/// deletion date goes to `$XDG_DATA_HOME/Trash/info`, so file managers can restore it too.
+///
+/// Files on other devices are copied into the home trash rather than into a per-device
+/// `.Trash-$uid` folder.
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs;
+    use std::io::{self, Write};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::DirBuilderExt;
+    use std::path::{Path, PathBuf};
+    use crate::config::schema::UndoConflict;
+    use crate::engine::mover::{get_unique_path, rename_or_copy, restore_target, Transfer};
+
+    const INFO_EXTENSION: &str = "trashinfo";
+
+    fn trash_dir() -> Result<PathBuf, String> {
+        let trash = dirs::data_dir()
+            .ok_or_else(|| "Cannot find the trash folder".to_string())?
+            .join("Trash");
+        for dir in [trash.join("files"), trash.join("info")] {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(&dir)
+                .map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        }
+        Ok(trash)
+    }
+
+    fn info_path(trash: &Path, trashed_name: &std::ffi::OsStr) -> PathBuf {
+        let mut name = trashed_name.to_os_string();
+        name.push(".");
+        name.push(INFO_EXTENSION);
+        trash.join("info").join(name)
+    }
+
+    pub fn trash_file(path: &Path, verify_hash: bool) -> Result<PathBuf, String> {
+        let original = std::env::current_dir().map_err(|e| e.to_string())?.join(path);
+        let trash = trash_dir()?;
+        let (trashed, info) = claim_name(&trash, &original)?;
+
+        let outcome = match rename_or_copy(&original, &trashed, verify_hash) {
+            Ok(Transfer::Moved) => Ok(trashed),
+            Ok(Transfer::SourceRemains(error)) => {
+                fs::remove_file(&trashed).ok();
+                Err(error)
+            }
+            Err(e) => Err(e),
+        };
+        if outcome.is_err() {
+            fs::remove_file(&info).ok();
+        }
+        outcome
+    }
+
+    /// Reserve a name in the trash by creating its info file, which the spec requires to
+    /// happen before the file itself is moved.
+    fn claim_name(trash: &Path, original: &Path) -> Result<(PathBuf, PathBuf), String> {
+        let contents = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            encode_path(original),
+            chrono::Local::now().format("%Y-%m-%dT%H:%M:%S"),
+        );
+
+        let name = original.file_name().ok_or_else(|| "Invalid file name".to_string())?;
+        let mut candidate = trash.join("files").join(name);
+        loop {
+            let is_taken = |p: &Path| p.exists() || info_path(trash, p.file_name().unwrap_or_default()).exists();
+            if is_taken(&candidate) {
+                candidate = get_unique_path(&candidate, is_taken);
+            }
+
+            let info = info_path(trash, candidate.file_name().unwrap_or_default());
+            match fs::OpenOptions::new().write(true).create_new(true).open(&info) {
+                Ok(mut file) => {
+                    file.write_all(contents.as_bytes())
+                        .and_then(|_| file.sync_all())
+                        .map_err(|e| {
+                            fs::remove_file(&info).ok();
+                            format!("Failed to write {:?}: {}", info, e)
+                        })?;
+                    return Ok((candidate, info));
+                }
+                // Another program trashed a file with the same name in the meantime
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(format!("Failed to write {:?}: {}", info, e)),
+            }
+        }
+    }
+
+    /// Percent-encode a path the way the `Path` key of a `.trashinfo` file expects.
+    fn encode_path(path: &Path) -> String {
+        let mut encoded = String::new();
+        for &byte in path.as_os_str().as_bytes() {
+            if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        encoded
+    }
+
+    pub fn restore_from_trash(
+        trashed: &Path,
+        original_location: &Path,
+        conflict: &UndoConflict,
+        verify_hash: bool,
+    ) -> Result<PathBuf, String> {
+        if !trashed.exists() {
+            return Err("File is no longer in the trash".to_string());
+        }
+
+        let target = restore_target(original_location, conflict)?;
+        match rename_or_copy(trashed, &target, verify_hash) {
+            Ok(Transfer::Moved) => {}
+            Ok(Transfer::SourceRemains(error)) => {
+                log::warn!("Restored {:?} but it is still in the trash: {}", target, error);
+                return Ok(target);
+            }
+            Err(e) => return Err(format!("Failed to restore file: {}", e)),
+        }
+
+        if let Some(trash) = trashed.parent().and_then(Path::parent) {
+            fs::remove_file(info_path(trash, trashed.file_name().unwrap_or_default())).ok();
+        }
+        Ok(target)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_encode_path() {
+            assert_eq!(encode_path(Path::new("/home/me/My File#1.txt")), "/home/me/My%20File%231.txt");
+            assert_eq!(encode_path(Path::new("/tmp/café")), "/tmp/caf%C3%A9");
+        }
+    }
+}
+
+/// Other platforms go through the `trash` crate. Only Windows can list the recycle bin,
+/// so that is the only one where trashed files can be restored.
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::path::{Path, PathBuf};
+    use crate::config::schema::UndoConflict;
+
+    pub fn trash_file(path: &Path, _verify_hash: bool) -> Result<PathBuf, String> {
+        trash::delete(path).map_err(|e| format!("Failed to move to trash: {}", e))?;
+        Ok(path.to_path_buf())
+    }
+
+    #[cfg(windows)]
+    pub fn restore_from_trash(
+        _trashed: &Path,
+        original_location: &Path,
+        _conflict: &UndoConflict,
+        _verify_hash: bool,
+    ) -> Result<PathBuf, String> {
+        // The recycle bin only restores to the original location
+        if original_location.exists() {
+            return Err("Original location already has a file".to_string());
+        }
+
+        let item = trash::os_limited::list()
+            .map_err(|e| format!("Failed to read the recycle bin: {}", e))?
+            .into_iter()
+            .filter(|item| item.original_path() == original_location)
+            .max_by_key(|item| item.time_deleted)
+            .ok_or_else(|| "File is no longer in the recycle bin".to_string())?;
+        trash::os_limited::restore_all([item])
+            .map_err(|e| format!("Failed to restore file: {}", e))?;
+        Ok(original_location.to_path_buf())
+    }
+
+    #[cfg(not(windows))]
+    pub fn restore_from_trash(
+        _trashed: &Path,
+        _original_location: &Path,
+        _conflict: &UndoConflict,
+        _verify_hash: bool,
+    ) -> Result<PathBuf, String> {
+        Err("Restoring from the trash is not supported on this platform".to_string())
+    }
+}
@@ -5,11 +5,11 @@ pub mod watcher;
 
 use commands::AppState;
 use config::load_config;
-use engine::History;
+use engine::{recover_moves, History};
 use watcher::FileWatcher;
 use parking_lot::Mutex;
 use std::sync::Arc;
-use tauri::Manager;
+use tauri::{Emitter, Manager};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -20,16 +20,28 @@ pub fn run() {
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_shell::init())
         .setup(|app| {
+            // Clean up after moves a crash interrupted before anything else touches the files
+            let move_lock = recover_moves();
+            
             let config = load_config();
             let history = Arc::new(History::new(config.history_limit));
             let watcher = FileWatcher::new(config, history.clone());
             
+            // Forward watcher events (e.g. conflicts that need a decision) to the UI
+            let handle = app.handle().clone();
+            watcher.set_event_handler(move |event| {
+                if let Err(e) = handle.emit(event.name(), &event) {
+                    log::warn!("Failed to emit {}: {}", event.name(), e);
+                }
+            });
+            
             // Start the background processor immediately - processes pending files even without watcher
             watcher.start_processor();
             
             app.manage(AppState {
                 watcher: Mutex::new(watcher),
                 history,
+                move_lock,
             });
             
             Ok(())
@@ -42,14 +54,25 @@ pub fn run() {
             commands::pause_watcher,
             commands::resume_watcher,
             commands::get_watcher_status,
+            commands::get_schedules,
             commands::get_pending_files,
             commands::cancel_pending_file,
             commands::move_file_now,
+            commands::resolve_conflict,
             commands::scan_folder,
+            commands::preview_scan,
+            commands::compact_now,
+            commands::find_duplicates,
+            commands::resolve_duplicates,
             commands::get_history,
             commands::get_recent_history,
             commands::get_history_stats,
             commands::undo_file_move,
+            commands::check_undo,
+            commands::undo_moves,
+            commands::redo_file_move,
+            commands::undo_last,
+            commands::redo_last,
             commands::clear_history,
             commands::get_rules,
             commands::add_rule,
@@ -1,37 +1,154 @@
 use notify::{Config, Event, RecommendedWatcher, RecursiveMode, Watcher};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::time::Duration;
+use std::sync::mpsc::channel;
+use std::time::{Duration, Instant};
 use parking_lot::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use chrono::Utc;
 use uuid::Uuid;
 
-use crate::config::schema::{Config as AppConfig, SortRule};
+use crate::config::schema::{
+    AfterExtract, Config as AppConfig, ConflictResolution, ExtractLimits, RuleAction, Schedule, SortRule,
+    WatchProfile,
+};
 use crate::config::save_config as save_app_config;
-use crate::engine::{match_file, move_file, History, MoveRecord};
+use crate::engine::{
+    apply_action, archive_stem, compact_profile, has_compaction, dispose_archive, expand_destination, expand_folder, extract_archive, match_file_at_depth, modified_time, plan_moves, renamed_file_name, History, MoveRecord,
+    CompactionSummary, MoveResult, MoveStatus, PlannedMove, RecordKind,
+};
+use crate::engine::mover::is_in_place;
+use crate::engine::rename::check_file_name;
+use crate::watcher::ignore_rules::IgnoreRules;
+use crate::watcher::pending_store::{load_pending, PendingStore, StoredPending};
+use crate::watcher::scheduler::{schedule_profile, ScheduleStatus, Scheduler};
+use crate::watcher::scope::WatchScope;
+use crate::watcher::stability::{poll_stability, FileSnapshot};
 
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct PendingFile {
     pub id: String,
+    pub profile_id: String,
     pub path: PathBuf,
     pub file_name: String,
+    /// Name the file will have once moved, after the rule's rename transforms
+    pub target_name: String,
     pub destination: String,
     pub rule_name: String,
+    pub action: RuleAction,
+    /// For `Extract` rules, with any placeholders in a `MoveTo` folder expanded
+    pub after_extract: AfterExtract,
     pub added_at: i64,
     pub move_at: i64,
     pub file_size: u64,
+    pub state: PendingState,
+    /// Set while `state` is `NeedsDecision`
+    pub conflict: Option<ConflictInfo>,
+    /// Polls in a row the file has been unchanged for
+    pub stable_checks: u32,
+    /// Groups files queued together (one scan, or one watcher session) for batch undo
+    pub batch_id: String,
+    /// Schedule that queued the file; it is matched against that schedule's folder and
+    /// rules when the queue is restored
+    pub schedule_id: Option<String>,
+    #[serde(skip)]
+    pub last_seen: Option<FileSnapshot>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub enum PendingState {
+    /// Waiting for the grace period to pass
+    Waiting,
+    /// The grace period is over but the file is still being written to
+    WaitingForStable,
+    /// The destination is taken and the user has to choose what to do
+    NeedsDecision,
+}
+
+/// Both sides of a name collision, so the UI can compare them.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConflictInfo {
+    pub existing_path: PathBuf,
+    pub existing_size: u64,
+    pub existing_modified: Option<i64>,
+    pub incoming_size: u64,
+    pub incoming_modified: Option<i64>,
+}
+
+/// How the user wants a conflict resolved.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum ConflictChoice {
+    /// Move the file under a name the user picked
+    Rename(String),
+    /// Leave the file where it is
+    Skip,
+    /// Replace the existing file
+    Overwrite,
+    /// Keep both, numbering the incoming file like "report (1).pdf"
+    KeepBoth,
+}
+
+/// Result of resolving one conflict.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConflictOutcome {
+    pub id: String,
+    pub file_name: String,
+    pub destination: Option<PathBuf>,
+    pub error: Option<String>,
+}
+
+/// Things the UI should hear about without polling.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type", content = "value")]
+pub enum WatcherEvent {
+    ConflictDetected(PendingFile),
+}
+
+impl WatcherEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WatcherEvent::ConflictDetected(_) => "conflict-detected",
+        }
+    }
+}
+
+/// How long after startup compaction first runs, and how often after that.
+const COMPACTION_DELAY: Duration = Duration::from_secs(5 * 60);
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+type EventHandler = Arc<RwLock<Option<Arc<dyn Fn(WatcherEvent) + Send + Sync>>>>;
+
+/// Per-profile state reported to the UI.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ProfileStatus {
+    pub id: String,
+    pub name: String,
+    pub watch_folder: PathBuf,
+    pub enabled: bool,
+    pub is_watching: bool,
+    pub error: Option<String>,
+    pub pending_count: usize,
 }
 
 pub struct FileWatcher {
-    watcher: Option<RecommendedWatcher>,
+    /// One OS watcher per profile that is currently being watched, keyed by profile id
+    watchers: HashMap<String, RecommendedWatcher>,
+    /// Why a profile could not be watched on the last `start`
+    watch_errors: HashMap<String, String>,
     pending_files: Arc<RwLock<HashMap<String, PendingFile>>>,
     history: Arc<History>,
     config: Arc<RwLock<AppConfig>>,
     is_running: Arc<RwLock<bool>>,
     is_paused: Arc<RwLock<bool>>,
     processor_started: Arc<RwLock<bool>>,
+    scheduler: Arc<RwLock<Scheduler>>,
+    /// Set while compaction runs, so a manual run and the daily one don't overlap
+    compacting: Arc<AtomicBool>,
+    event_handler: EventHandler,
+    /// Batch id given to files picked up by watching, as opposed to a scan
+    session_id: String,
 }
 
 impl FileWatcher {
@@ -42,15 +159,25 @@ impl FileWatcher {
         let processor_started = Arc::new(RwLock::new(false));
 
         Self {
-            watcher: None,
+            watchers: HashMap::new(),
+            watch_errors: HashMap::new(),
             pending_files,
             history,
             config: app_config,
             is_running: Arc::new(RwLock::new(false)),
             is_paused,
             processor_started,
+            scheduler: Arc::new(RwLock::new(Scheduler::load())),
+            compacting: Arc::new(AtomicBool::new(false)),
+            event_handler: Arc::new(RwLock::new(None)),
+            session_id: Uuid::new_v4().to_string(),
         }
     }
+    
+    /// Register a callback for watcher events, e.g. to forward them to the UI.
+    pub fn set_event_handler(&self, handler: impl Fn(WatcherEvent) + Send + Sync + 'static) {
+        *self.event_handler.write() = Some(Arc::new(handler));
+    }
 
     /// Start the background processor that moves files after grace period.
     /// This runs independently of the watcher - so Scan works even without Start.
@@ -68,12 +195,30 @@ impl FileWatcher {
         let app_config = self.config.clone();
         let history = self.history.clone();
         let is_paused = self.is_paused.clone();
+        let scheduler = self.scheduler.clone();
+        let event_handler = self.event_handler.clone();
+
+        // Pick up whatever was queued when the app last quit
+        let saved = load_pending();
+        let restored = restore_pending(&pending_files, &history, &app_config.read(), &saved);
+        if restored > 0 {
+            log::info!("Restored {} pending files", restored);
+        }
+        let mut store = PendingStore::new(saved);
+        let mut next_compaction = Instant::now() + COMPACTION_DELAY;
+        let compacting = self.compacting.clone();
 
         std::thread::spawn(move || {
             loop {
                 if !*is_paused.read() {
-                    process_pending_files(&pending_files, &app_config, &history);
+                    run_due_schedules(&pending_files, &app_config, &history, &scheduler);
+                    process_pending_files(&pending_files, &app_config, &history, &event_handler);
+                    if Instant::now() >= next_compaction {
+                        next_compaction = Instant::now() + COMPACTION_INTERVAL;
+                        start_compaction(&app_config, &history, &compacting);
+                    }
                 }
+                store.sync(&pending_files.read());
                 std::thread::sleep(Duration::from_millis(500)); // Check every 500ms for faster response
             }
         });
@@ -83,6 +228,7 @@ impl FileWatcher {
     
     pub fn update_config(&self, config: AppConfig) {
         *self.config.write() = config;
+        IgnoreRules::clear_cache();
     }
     
     pub fn start(&mut self) -> Result<(), String> {
@@ -90,53 +236,70 @@ impl FileWatcher {
             return Ok(());
         }
         
-        let config = self.config.read().clone();
-        let watch_path = config.watch_folder.clone();
-        
-        if !watch_path.exists() {
-            return Err(format!("Watch folder does not exist: {:?}", watch_path));
-        }
+        let profiles: Vec<WatchProfile> = self.config.read()
+            .all_profiles()
+            .into_iter()
+            .filter(|p| p.enabled)
+            .collect();
         
         let pending_files = self.pending_files.clone();
         let app_config = self.config.clone();
+        let history = self.history.clone();
         
-        let (tx, rx): (Sender<Result<Event, notify::Error>>, Receiver<Result<Event, notify::Error>>) = channel();
+        let (tx, rx) = channel::<(String, Result<Event, notify::Error>)>();
         
-        let mut watcher = RecommendedWatcher::new(
-            move |res| {
-                let _ = tx.send(res);
-            },
-            Config::default().with_poll_interval(Duration::from_secs(1)),
-        ).map_err(|e| format!("Failed to create watcher: {}", e))?;
+        self.watch_errors.clear();
+        for profile in &profiles {
+            match watch_profile(profile, tx.clone()) {
+                Ok(watcher) => {
+                    log::info!("File watcher started for: {:?}", profile.watch_folder);
+                    self.watchers.insert(profile.id.clone(), watcher);
+                }
+                Err(e) => {
+                    log::error!("Failed to watch profile {}: {}", profile.name, e);
+                    self.watch_errors.insert(profile.id.clone(), e);
+                }
+            }
+        }
         
-        watcher.watch(&watch_path, RecursiveMode::NonRecursive)
-            .map_err(|e| format!("Failed to watch folder: {}", e))?;
+        if self.watchers.is_empty() {
+            let errors: Vec<String> = self.watch_errors.values().cloned().collect();
+            return Err(errors.join("; "));
+        }
         
-        self.watcher = Some(watcher);
         *self.is_running.write() = true;
         
         // Spawn event handler thread
         let is_running = self.is_running.clone();
         let is_paused = self.is_paused.clone();
+        let session_id = self.session_id.clone();
         
         std::thread::spawn(move || {
             while *is_running.read() {
                 match rx.recv_timeout(Duration::from_millis(100)) {
-                    Ok(Ok(event)) => {
+                    Ok((profile_id, Ok(event))) => {
                         if *is_paused.read() {
                             continue;
                         }
                         
+                        let Some(profile) = app_config.read().profile(&profile_id) else {
+                            continue;
+                        };
+                        let scope = WatchScope::from_profile(&profile);
+                        
                         for path in event.paths {
-                            if path.is_file() && is_valid_file(&path) {
-                                let config = app_config.read();
-                                if let Some(rule) = match_file(&path, &config.rules) {
-                                    add_pending_file(&pending_files, &path, rule, config.grace_period_seconds);
-                                }
+                            if !path.is_file() {
+                                continue;
+                            }
+                            let Some(depth) = scope.depth_of(&path) else {
+                                continue;
+                            };
+                            if let Some(rule) = match_file_at_depth(&path, depth, &profile.rules) {
+                                add_pending_file(&pending_files, &history, &profile, &path, rule, &session_id, None);
                             }
                         }
                     }
-                    Ok(Err(e)) => {
+                    Ok((_, Err(e))) => {
                         log::error!("Watch error: {}", e);
                     }
                     Err(_) => {
@@ -146,13 +309,12 @@ impl FileWatcher {
             }
         });
         
-        log::info!("File watcher started for: {:?}", watch_path);
         Ok(())
     }
     
     pub fn stop(&mut self) {
         *self.is_running.write() = false;
-        self.watcher = None;
+        self.watchers.clear();
         log::info!("File watcher stopped");
     }
     
@@ -172,8 +334,31 @@ impl FileWatcher {
         *self.is_paused.read()
     }
     
-    pub fn get_pending_files(&self) -> Vec<PendingFile> {
-        self.pending_files.read().values().cloned().collect()
+    /// Pending files of one profile, or of every profile when `profile_id` is `None`.
+    pub fn get_pending_files(&self, profile_id: Option<&str>) -> Vec<PendingFile> {
+        self.pending_files.read()
+            .values()
+            .filter(|f| match profile_id {
+                Some(id) => f.profile_id == id,
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+    
+    pub fn profile_statuses(&self) -> Vec<ProfileStatus> {
+        let profiles = self.config.read().all_profiles();
+        let pending = self.pending_files.read();
+        
+        profiles.into_iter().map(|profile| ProfileStatus {
+            is_watching: self.watchers.contains_key(&profile.id),
+            error: self.watch_errors.get(&profile.id).cloned(),
+            pending_count: pending.values().filter(|f| f.profile_id == profile.id).count(),
+            id: profile.id,
+            name: profile.name,
+            watch_folder: profile.watch_folder,
+            enabled: profile.enabled,
+        }).collect()
     }
     
     pub fn cancel_pending(&self, id: &str) -> bool {
@@ -181,13 +366,13 @@ impl FileWatcher {
     }
     
     /// Remove pending file and return it with config needed for move. Caller does the move outside the lock to avoid blocking pause/other commands.
-    pub fn take_pending_for_move(&self, id: &str) -> Option<(PendingFile, PathBuf, crate::config::schema::ConflictResolution)> {
+    pub fn take_pending_for_move(&self, id: &str) -> Option<(PendingFile, PathBuf, ConflictResolution)> {
         let pending = self.pending_files.write().remove(id)?;
-        let config = self.config.read();
+        let profile = self.config.read().profile(&pending.profile_id)?;
         Some((
             pending,
-            config.destination_root.clone(),
-            config.conflict_resolution.clone(),
+            profile.destination_root,
+            profile.conflict_resolution,
         ))
     }
 
@@ -207,45 +392,117 @@ impl FileWatcher {
             None => return Err("Pending file not found".to_string()),
         };
 
+        self.move_pending(pending, &destination_root, &conflict_resolution).map(|_| ())
+    }
+    
+    /// Move a pending file that has already been taken out of the queue. On failure the
+    /// file is put back so the user can retry.
+    fn move_pending(
+        &self,
+        mut pending: PendingFile,
+        destination_root: &Path,
+        conflict_resolution: &ConflictResolution,
+    ) -> Result<PathBuf, String> {
         // Do the actual move *outside* the watcher lock so pause/status don't block
-        let result = move_file(
-            &pending.path,
-            &destination_root,
-            &pending.destination,
-            &conflict_resolution,
-        );
+        let settings = ActionSettings::from_config(&self.config.read());
+        let (result, kind) = run_action(&pending, destination_root, conflict_resolution, &settings);
 
         if result.success {
-            let record = MoveRecord {
-                id: Uuid::new_v4().to_string(),
-                original_path: result.source,
-                new_path: result.destination,
-                rule_name: pending.rule_name,
-                timestamp: Utc::now(),
-                file_size: pending.file_size,
-                can_undo: true,
-            };
-            self.record_successful_move(record);
-            Ok(())
-        } else {
+            if let Some(error) = &result.error {
+                log::warn!("Copied file: {} - {}", pending.file_name, error);
+            }
+            self.record_successful_move(move_record(&pending, &result, kind));
+            return Ok(result.destination);
+        }
+        
+        match result.status {
+            MoveStatus::AlreadyMirrored | MoveStatus::AlreadyInPlace => return Ok(result.destination),
+            MoveStatus::NeedsDecision => {
+                mark_needs_decision(&mut pending, &result.destination);
+                if let Some(handler) = self.event_handler.read().clone() {
+                    handler(WatcherEvent::ConflictDetected(pending.clone()));
+                }
+                let _ = self.pending_files.write().insert(pending.id.clone(), pending);
+            }
             // Put it back so user can retry
-            let _ = self.pending_files.write().insert(pending.id.clone(), pending);
-            Err(result.error.unwrap_or_else(|| "Unknown error".to_string()))
+            _ => {
+                let _ = self.pending_files.write().insert(pending.id.clone(), pending);
+            }
         }
+        Err(result.error.unwrap_or_else(|| "Unknown error".to_string()))
     }
     
-    pub fn scan_folder(&self) -> Vec<PendingFile> {
-        let config = self.config.read();
+    /// Settle a conflict the user was asked about. With `apply_to_all`, the same choice is
+    /// applied to every file currently waiting for a decision; a custom name only makes
+    /// sense for one file, so the others are kept side by side instead.
+    pub fn resolve_conflict(&self, id: &str, choice: ConflictChoice, apply_to_all: bool) -> Vec<ConflictOutcome> {
+        let mut ids: Vec<String> = vec![id.to_string()];
+        if apply_to_all {
+            ids.extend(
+                self.pending_files.read()
+                    .values()
+                    .filter(|f| f.state == PendingState::NeedsDecision && f.id != id)
+                    .map(|f| f.id.clone()),
+            );
+        }
+        
+        ids.into_iter().map(|pending_id| {
+            let choice = match (&choice, pending_id == id) {
+                (ConflictChoice::Rename(_), false) => ConflictChoice::KeepBoth,
+                (choice, _) => choice.clone(),
+            };
+            self.resolve_one(&pending_id, choice)
+        }).collect()
+    }
+    
+    fn resolve_one(&self, id: &str, choice: ConflictChoice) -> ConflictOutcome {
+        let mut outcome = ConflictOutcome {
+            id: id.to_string(),
+            file_name: String::new(),
+            destination: None,
+            error: None,
+        };
+        
+        // Check a new name before taking the file off the queue, so it can be renamed again
+        if let ConflictChoice::Rename(name) = &choice {
+            if let Err(e) = check_file_name(name) {
+                outcome.file_name = self.pending_files.read()
+                    .get(id)
+                    .map(|f| f.file_name.clone())
+                    .unwrap_or_default();
+                outcome.error = Some(e);
+                return outcome;
+            }
+        }
+        
+        let Some((mut pending, destination_root, _)) = self.take_pending_for_move(id) else {
+            outcome.error = Some("Pending file not found".to_string());
+            return outcome;
+        };
+        outcome.file_name = pending.file_name.clone();
+        
+        let Some(policy) = apply_choice(&mut pending, choice) else {
+            log::info!("Skipped conflicting file: {}", pending.file_name);
+            return outcome;
+        };
+        match self.move_pending(pending, &destination_root, &policy) {
+            Ok(destination) => outcome.destination = Some(destination),
+            Err(e) => outcome.error = Some(e),
+        }
+        outcome
+    }
+    
+    /// Queue every matching file in one profile's folder, or in all enabled profiles when
+    /// `profile_id` is `None`.
+    pub fn scan_folder(&self, profile_id: Option<&str>) -> Vec<PendingFile> {
         let mut added = Vec::new();
+        let batch_id = Uuid::new_v4().to_string();
         
-        if let Ok(entries) = std::fs::read_dir(&config.watch_folder) {
-            for entry in entries.flatten() {
-                let path = entry.path();
-                if path.is_file() && is_valid_file(&path) {
-                    if let Some(rule) = match_file(&path, &config.rules) {
-                        if let Some(pending) = add_pending_file(&self.pending_files, &path, rule, config.grace_period_seconds) {
-                            added.push(pending);
-                        }
+        for profile in self.scan_targets(profile_id) {
+            for (path, depth) in collect_candidates(&profile) {
+                if let Some(rule) = match_file_at_depth(&path, depth, &profile.rules) {
+                    if let Some(pending) = add_pending_file(&self.pending_files, &self.history, &profile, &path, rule, &batch_id, None) {
+                        added.push(pending);
                     }
                 }
             }
@@ -253,26 +510,92 @@ impl FileWatcher {
         
         added
     }
+    
+    /// Work out what `scan_folder` followed by the moves would do, without queueing
+    /// or moving anything.
+    pub fn plan_scan(&self, profile_id: Option<&str>) -> Vec<PlannedMove> {
+        self.scan_targets(profile_id)
+            .iter()
+            .flat_map(|profile| {
+                let mut files = collect_candidates(profile);
+                files.retain(|(path, depth)| {
+                    let rule = match_file_at_depth(path, *depth, &profile.rules);
+                    !rule.is_some_and(|r| r.action == RuleAction::Extract && self.history.already_extracted(path))
+                });
+                plan_moves(&files, &profile.rules, &profile.destination_root, &profile.conflict_resolution)
+            })
+            .collect()
+    }
+    
+    /// Archive old files for the rules with compaction in one profile, or in all enabled
+    /// profiles when `profile_id` is `None`.
+    pub fn compact(&self, profile_id: Option<&str>) -> CompactionSummary {
+        let mut summary = CompactionSummary::default();
+        let Some(_running) = CompactionGuard::acquire(&self.compacting) else {
+            summary.errors.push("Compaction is already running".to_string());
+            return summary;
+        };
+        for profile in self.scan_targets(profile_id) {
+            summary.merge(compact_profile(&profile, &self.history));
+        }
+        summary
+    }
+    
+    /// Every configured schedule with when it last ran and will run next.
+    pub fn schedule_statuses(&self) -> Vec<ScheduleStatus> {
+        self.scheduler.read().statuses(&self.config.read())
+    }
+    
+    fn scan_targets(&self, profile_id: Option<&str>) -> Vec<WatchProfile> {
+        let config = self.config.read();
+        match profile_id {
+            Some(id) => config.profile(id).into_iter().collect(),
+            None => config.all_profiles().into_iter().filter(|p| p.enabled).collect(),
+        }
+    }
 }
 
-fn is_valid_file(path: &Path) -> bool {
-    let file_name = path.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
-    
-    // Skip hidden files, temp files, and partial downloads
-    !file_name.starts_with('.') &&
-    !file_name.ends_with(".crdownload") &&
-    !file_name.ends_with(".part") &&
-    !file_name.ends_with(".tmp") &&
-    !file_name.ends_with(".download")
+fn watch_profile(
+    profile: &WatchProfile,
+    tx: std::sync::mpsc::Sender<(String, Result<Event, notify::Error>)>,
+) -> Result<RecommendedWatcher, String> {
+    if !profile.watch_folder.exists() {
+        return Err(format!("Watch folder does not exist: {:?}", profile.watch_folder));
+    }
+    
+    let profile_id = profile.id.clone();
+    let mut watcher = RecommendedWatcher::new(
+        move |res| {
+            let _ = tx.send((profile_id.clone(), res));
+        },
+        Config::default().with_poll_interval(Duration::from_secs(1)),
+    ).map_err(|e| format!("Failed to create watcher: {}", e))?;
+    
+    let mode = if WatchScope::from_profile(profile).is_recursive() {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    
+    watcher.watch(&profile.watch_folder, mode)
+        .map_err(|e| format!("Failed to watch folder: {}", e))?;
+    
+    Ok(watcher)
+}
+
+/// Files under a profile's watch folder that are eligible for sorting, with their depth.
+fn collect_candidates(profile: &WatchProfile) -> Vec<(PathBuf, usize)> {
+    WatchScope::from_profile(profile).collect_files()
 }
 
 fn add_pending_file(
     pending_files: &Arc<RwLock<HashMap<String, PendingFile>>>,
+    history: &History,
+    profile: &WatchProfile,
     path: &Path,
     rule: &SortRule,
-    grace_period: u64,
+    batch_id: &str,
+    schedule_id: Option<&str>,
 ) -> Option<PendingFile> {
     // Check if already pending
     {
@@ -281,49 +604,451 @@ fn add_pending_file(
             return None;
         }
     }
+    // A kept archive would otherwise be unpacked again by every scan
+    if rule.action == RuleAction::Extract && history.already_extracted(path) {
+        return None;
+    }
+    
+    let mut pending = new_pending_file(profile, path, rule, batch_id)?;
+    pending.schedule_id = schedule_id.map(str::to_string);
+    let result = pending.clone();
+    pending_files.write().insert(pending.id.clone(), pending);
+    Some(result)
+}
+
+fn new_pending_file(profile: &WatchProfile, path: &Path, rule: &SortRule, batch_id: &str) -> Option<PendingFile> {
+    let file_name = path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown")
+        .to_string();
+    
+    let (destination, target_name) = match rule.action {
+        // Matching a `Leave` rule is what keeps the file where it is
+        RuleAction::Leave => return None,
+        RuleAction::Trash | RuleAction::Delete => (String::new(), file_name.clone()),
+        _ => {
+            let destination = match expand_destination(rule, path) {
+                Ok(destination) => destination,
+                Err(e) => {
+                    log::warn!("Cannot sort {:?} with rule {}: {}", path, rule.name, e);
+                    return None;
+                }
+            };
+            
+            let target_name = match renamed_file_name(rule, path) {
+                Ok(name) => name,
+                Err(e) => {
+                    log::warn!("Cannot rename {:?} with rule {}: {}", path, rule.name, e);
+                    return None;
+                }
+            };
+            // A schedule sweeping a rule's own destination finds its files already sorted
+            let target = profile.destination_root.join(&destination).join(&target_name);
+            if rule.action != RuleAction::Extract && is_in_place(path, &target) {
+                return None;
+            }
+            (destination, target_name)
+        }
+    };
+    
+    let after_extract = match &rule.after_extract {
+        AfterExtract::MoveTo(folder) if rule.action == RuleAction::Extract => {
+            match expand_folder(folder, rule, path) {
+                Ok(folder) => AfterExtract::MoveTo(folder),
+                Err(e) => {
+                    log::warn!("Cannot sort {:?} with rule {}: {}", path, rule.name, e);
+                    return None;
+                }
+            }
+        }
+        after if rule.action == RuleAction::Extract => after.clone(),
+        _ => AfterExtract::Keep,
+    };
     
     let now = Utc::now().timestamp();
     let file_size = path.metadata().map(|m| m.len()).unwrap_or(0);
     
-    let pending = PendingFile {
+    Some(PendingFile {
         id: Uuid::new_v4().to_string(),
+        profile_id: profile.id.clone(),
         path: path.to_path_buf(),
-        file_name: path.file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("unknown")
-            .to_string(),
-        destination: rule.destination_folder.clone(),
+        file_name,
+        target_name,
+        destination,
         rule_name: rule.name.clone(),
+        action: rule.action,
+        after_extract,
         added_at: now,
-        move_at: now + grace_period as i64,
+        move_at: now + profile.grace_period_seconds as i64,
         file_size,
+        state: PendingState::Waiting,
+        conflict: None,
+        stable_checks: 0,
+        batch_id: batch_id.to_string(),
+        schedule_id: None,
+        last_seen: FileSnapshot::of(path),
+    })
+}
+
+/// Re-queue files saved by a previous run. Entries whose file, profile or schedule is
+/// gone, that no rule matches any more, or that were already extracted are dropped; the
+/// others are matched against the current rules again but keep their id and original
+/// deadline.
+fn restore_pending(
+    pending_files: &Arc<RwLock<HashMap<String, PendingFile>>>,
+    history: &History,
+    config: &AppConfig,
+    saved: &[StoredPending],
+) -> usize {
+    let mut restored = 0;
+    
+    for entry in saved {
+        let pending = restore_entry(history, config, entry).and_then(|pending| {
+            let mut files = pending_files.write();
+            if files.values().any(|f| f.path == pending.path) {
+                return Err("it is already queued".to_string());
+            }
+            files.insert(pending.id.clone(), pending);
+            Ok(())
+        });
+        match pending {
+            Ok(()) => restored += 1,
+            Err(reason) => log::info!("Dropping {:?} from the queue: {}", entry.path, reason),
+        }
+    }
+    
+    restored
+}
+
+fn restore_entry(history: &History, config: &AppConfig, entry: &StoredPending) -> Result<PendingFile, String> {
+    // Files a schedule queued are in its folder, and only its rules apply to them
+    let profile = match &entry.schedule_id {
+        Some(id) => config.schedules.iter()
+            .find(|s| s.id == *id)
+            .ok_or_else(|| "its schedule was removed".to_string())
+            .and_then(|schedule| schedule_profile(config, schedule))?,
+        None => config.profile(&entry.profile_id).ok_or_else(|| "its profile was removed".to_string())?,
     };
+    if !entry.path.is_file() {
+        return Err("the file is gone".to_string());
+    }
+    let depth = WatchScope::from_profile(&profile).depth_of(&entry.path)
+        .ok_or_else(|| "it is no longer in a watched folder".to_string())?;
+    let rule = match_file_at_depth(&entry.path, depth, &profile.rules)
+        .ok_or_else(|| "no rule matches it any more".to_string())?;
+    if rule.name != entry.rule_name {
+        log::info!("{:?} now matches rule {} instead of {}", entry.path, rule.name, entry.rule_name);
+    }
+    if rule.action == RuleAction::Extract && history.already_extracted(&entry.path) {
+        return Err("it has already been extracted".to_string());
+    }
     
-    let result = pending.clone();
-    pending_files.write().insert(pending.id.clone(), pending);
-    Some(result)
+    let mut pending = new_pending_file(&profile, &entry.path, rule, &entry.batch_id)
+        .ok_or_else(|| format!("rule {} can't sort it", rule.name))?;
+    pending.id = entry.id.clone();
+    pending.added_at = entry.added_at;
+    pending.move_at = entry.move_at;
+    pending.schedule_id = entry.schedule_id.clone();
+    Ok(pending)
+}
+
+fn mark_needs_decision(pending: &mut PendingFile, existing: &Path) {
+    let modified = |path: &Path| {
+        path.metadata().ok()
+            .and_then(|m| m.modified().ok())
+            .map(|t| chrono::DateTime::<Utc>::from(t).timestamp())
+    };
+    
+    pending.state = PendingState::NeedsDecision;
+    pending.conflict = Some(ConflictInfo {
+        existing_path: existing.to_path_buf(),
+        existing_size: existing.metadata().map(|m| m.len()).unwrap_or(0),
+        existing_modified: modified(existing),
+        incoming_size: pending.path.metadata().map(|m| m.len()).unwrap_or(pending.file_size),
+        incoming_modified: modified(&pending.path),
+    });
+}
+
+/// Prepare a file waiting for a decision to be moved as the user chose. Returns the
+/// conflict policy to move it with, or `None` if it should stay where it is.
+fn apply_choice(pending: &mut PendingFile, choice: ConflictChoice) -> Option<ConflictResolution> {
+    let policy = match choice {
+        ConflictChoice::Skip => return None,
+        ConflictChoice::Rename(name) => {
+            pending.target_name = name;
+            // Ask again if the new name is taken too
+            ConflictResolution::Ask
+        }
+        ConflictChoice::Overwrite => ConflictResolution::Overwrite,
+        ConflictChoice::KeepBoth => ConflictResolution::Rename,
+    };
+    pending.state = PendingState::Waiting;
+    pending.conflict = None;
+    Some(policy)
+}
+
+/// Settings every rule action needs, taken from the config.
+#[derive(Clone)]
+struct ActionSettings {
+    verify_copies: bool,
+    extract_limits: ExtractLimits,
+}
+
+impl ActionSettings {
+    fn from_config(config: &AppConfig) -> Self {
+        Self {
+            verify_copies: config.verify_copies,
+            extract_limits: config.extract_limits.clone(),
+        }
+    }
+}
+
+/// Carry out a pending file's rule action. Returns the result along with the kind of
+/// history record it should get.
+fn run_action(
+    pending: &PendingFile,
+    destination_root: &Path,
+    conflict_resolution: &ConflictResolution,
+    settings: &ActionSettings,
+) -> (MoveResult, RecordKind) {
+    if pending.action != RuleAction::Extract {
+        let result = apply_action(
+            pending.action,
+            &pending.path,
+            destination_root,
+            &pending.destination,
+            &pending.target_name,
+            conflict_resolution,
+            settings.verify_copies,
+        );
+        let kind = record_kind(&result);
+        return (result, kind);
+    }
+    
+    let dest_dir = destination_root.join(&pending.destination);
+    let folder_name = archive_stem(&pending.target_name).unwrap_or(&pending.target_name);
+    let mut result = MoveResult {
+        source: pending.path.clone(),
+        destination: dest_dir.join(folder_name),
+        success: false,
+        status: MoveStatus::Failed,
+        error: None,
+        duplicate_of: None,
+    };
+    
+    let extraction = match extract_archive(&pending.path, &dest_dir, folder_name, &settings.extract_limits) {
+        Ok(extraction) => extraction,
+        Err(e) => {
+            result.error = Some(e);
+            return (result, RecordKind::Move);
+        }
+    };
+    log::info!("Unpacked {} files ({} bytes) from {}", extraction.files, extraction.bytes, pending.file_name);
+    result.success = true;
+    result.status = MoveStatus::Extracted;
+    result.destination = extraction.folder;
+    
+    // The archive is already unpacked, so failing to move it isn't worth undoing that for
+    let archive_moved_to = dispose_archive(
+        &pending.path,
+        &pending.after_extract,
+        destination_root,
+        conflict_resolution,
+        settings.verify_copies,
+    )
+    .unwrap_or_else(|e| {
+        log::warn!("Extracted {} but could not move it: {}", pending.file_name, e);
+        None
+    });
+    let kind = RecordKind::Extracted {
+        archive_trashed: pending.after_extract == AfterExtract::Trash && archive_moved_to.is_some(),
+        archive_moved_to,
+    };
+    (result, kind)
+}
+
+/// What kind of history record a successful `apply_action` makes.
+fn record_kind(result: &MoveResult) -> RecordKind {
+    match (&result.duplicate_of, result.status) {
+        (Some(existing), _) => RecordKind::Duplicate {
+            duplicate_of: existing.clone(),
+            deleted: result.destination == *existing,
+        },
+        (None, MoveStatus::Copied) => RecordKind::Copy,
+        (None, MoveStatus::Hardlinked) => RecordKind::Hardlink,
+        (None, MoveStatus::Symlinked) => RecordKind::Symlink,
+        (None, MoveStatus::Trashed) => RecordKind::Trashed,
+        (None, MoveStatus::Deleted) => RecordKind::Deleted,
+        (None, _) => RecordKind::Move,
+    }
+}
+
+/// History entry for a pending file that `result` handled successfully.
+fn move_record(pending: &PendingFile, result: &MoveResult, kind: RecordKind) -> MoveRecord {
+    // For an extraction the destination is a folder, so the archive's size is more useful
+    let file_size = match kind {
+        RecordKind::Extracted { .. } => pending.file_size,
+        _ => result.destination.metadata().map(|m| m.len()).unwrap_or(pending.file_size),
+    };
+    
+    MoveRecord {
+        id: Uuid::new_v4().to_string(),
+        original_path: result.source.clone(),
+        new_path: result.destination.clone(),
+        rule_name: pending.rule_name.clone(),
+        timestamp: Utc::now(),
+        file_size,
+        can_undo: kind != RecordKind::Deleted,
+        original_name: pending.file_name.clone(),
+        new_name: result.destination
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        kind,
+        batch_id: Some(pending.batch_id.clone()).filter(|b| !b.is_empty()),
+        modified: modified_time(&result.destination),
+        profile_id: Some(pending.profile_id.clone()),
+    }
+}
+
+/// Queue the files of every schedule that is due. They have no grace period, so the
+/// processor picks them up on the same pass.
+fn run_due_schedules(
+    pending_files: &Arc<RwLock<HashMap<String, PendingFile>>>,
+    app_config: &Arc<RwLock<AppConfig>>,
+    history: &History,
+    scheduler: &Arc<RwLock<Scheduler>>,
+) {
+    let now = Utc::now();
+    let due = scheduler.read().due(&app_config.read().schedules, now);
+    if due.is_empty() {
+        return;
+    }
+    
+    let config = app_config.read().clone();
+    for schedule in due {
+        let outcome = queue_schedule(pending_files, history, &config, &schedule);
+        match &outcome {
+            Ok(queued) => log::info!("Schedule {} queued {} files", schedule.name, queued),
+            Err(e) => log::warn!("Schedule {} failed: {}", schedule.name, e),
+        }
+        scheduler.write().record_run(&schedule.id, now, outcome);
+    }
+}
+
+fn queue_schedule(
+    pending_files: &Arc<RwLock<HashMap<String, PendingFile>>>,
+    history: &History,
+    config: &AppConfig,
+    schedule: &Schedule,
+) -> Result<usize, String> {
+    let profile = schedule_profile(config, schedule)?;
+    if !profile.watch_folder.is_dir() {
+        return Err(format!("{:?} is not a folder", profile.watch_folder));
+    }
+    
+    // One batch per run, so a whole run can be undone at once
+    let batch_id = Uuid::new_v4().to_string();
+    let mut queued = 0;
+    for (path, depth) in collect_candidates(&profile) {
+        if let Some(rule) = match_file_at_depth(&path, depth, &profile.rules) {
+            if add_pending_file(pending_files, history, &profile, &path, rule, &batch_id, Some(&schedule.id)).is_some() {
+                queued += 1;
+            }
+        }
+    }
+    Ok(queued)
+}
+
+/// Compact every enabled profile on its own thread, since packing a month of files can
+/// take a while. Does nothing if the previous run is still going.
+fn start_compaction(app_config: &Arc<RwLock<AppConfig>>, history: &Arc<History>, compacting: &Arc<AtomicBool>) {
+    let profiles: Vec<WatchProfile> = app_config.read()
+        .all_profiles()
+        .into_iter()
+        .filter(|p| p.enabled && has_compaction(p))
+        .collect();
+    if profiles.is_empty() {
+        return;
+    }
+    let Some(running) = CompactionGuard::acquire(compacting) else {
+        return;
+    };
+
+    let history = history.clone();
+    std::thread::spawn(move || {
+        let _running = running;
+        for profile in &profiles {
+            let summary = compact_profile(profile, &history);
+            if summary.files > 0 {
+                log::info!(
+                    "Compacted {} files ({} bytes) in {} into {} archives",
+                    summary.files, summary.bytes, profile.name, summary.archives.len()
+                );
+            }
+            for error in &summary.errors {
+                log::warn!("Compaction of {}: {}", profile.name, error);
+            }
+        }
+    });
+}
+
+/// Holds the compaction flag and clears it when dropped, even if compaction panicked,
+/// so a failed run doesn't stop compaction for good.
+struct CompactionGuard(Arc<AtomicBool>);
+
+impl CompactionGuard {
+    /// `None` if compaction is already running.
+    fn acquire(flag: &Arc<AtomicBool>) -> Option<Self> {
+        (!flag.swap(true, Ordering::SeqCst)).then(|| Self(flag.clone()))
+    }
+}
+
+impl Drop for CompactionGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
 }
 
 fn process_pending_files(
     pending_files: &Arc<RwLock<HashMap<String, PendingFile>>>,
     app_config: &Arc<RwLock<AppConfig>>,
     history: &Arc<History>,
+    event_handler: &EventHandler,
 ) {
     let now = Utc::now().timestamp();
     
     // Get config snapshot once
-    let (destination_root, conflict_resolution) = {
+    let (profiles, required_checks, settings) = {
         let config = app_config.read();
-        (config.destination_root.clone(), config.conflict_resolution.clone())
+        let profiles: HashMap<String, WatchProfile> = config
+            .all_profiles()
+            .into_iter()
+            .map(|p| (p.id.clone(), p))
+            .collect();
+        (profiles, config.stable_checks, ActionSettings::from_config(&config))
     };
     
-    let to_move: Vec<PendingFile> = {
+    let due: Vec<PendingFile> = {
         let files = pending_files.read();
         files.values()
-            .filter(|f| f.move_at <= now)
+            .filter(|f| f.move_at <= now && f.state != PendingState::NeedsDecision)
             .cloned()
             .collect()
     };
+    
+    // Files still being written to stay queued; vanished files go through so they get dropped
+    let mut to_move = Vec::new();
+    for mut pending in due {
+        if !pending.path.exists() || poll_stability(&mut pending, required_checks) {
+            to_move.push(pending);
+        } else if let Some(queued) = pending_files.write().get_mut(&pending.id) {
+            queued.state = pending.state;
+            queued.stable_checks = pending.stable_checks;
+            queued.last_seen = pending.last_seen;
+            queued.file_size = pending.file_size;
+        }
+    }
 
     if to_move.is_empty() {
         return;
@@ -333,41 +1058,59 @@ fn process_pending_files(
     
     // Process files - spawn threads for parallel moves
     let handles: Vec<_> = to_move.into_iter().map(|pending| {
-        let dest_root = destination_root.clone();
-        let conflict_res = conflict_resolution.clone();
+        let profile = profiles.get(&pending.profile_id);
+        let dest_root = profile.map(|p| p.destination_root.clone());
+        let conflict_res = profile.map(|p| p.conflict_resolution.clone()).unwrap_or(ConflictResolution::Rename);
         let history_clone = history.clone();
         let pending_files_clone = pending_files.clone();
+        let event_handler = event_handler.read().clone();
+        let settings = settings.clone();
         
         std::thread::spawn(move || {
-            // Check if file still exists
-            if !pending.path.exists() {
+            // Check if file still exists and its profile hasn't been removed
+            let Some(dest_root) = dest_root.filter(|_| pending.path.exists()) else {
                 pending_files_clone.write().remove(&pending.id);
                 return false;
-            }
+            };
             
-            let result = move_file(
-                &pending.path,
-                &dest_root,
-                &pending.destination,
-                &conflict_res,
-            );
+            let mut pending = pending;
+            let (result, kind) = run_action(&pending, &dest_root, &conflict_res, &settings);
+            
+            // Keep it queued until the user decides what to do with the conflict
+            if result.status == MoveStatus::NeedsDecision {
+                mark_needs_decision(&mut pending, &result.destination);
+                pending_files_clone.write().insert(pending.id.clone(), pending.clone());
+                log::info!("Waiting for a decision on: {}", pending.file_name);
+                if let Some(handler) = event_handler {
+                    handler(WatcherEvent::ConflictDetected(pending));
+                }
+                return false;
+            }
             
             // Remove from pending after move attempt
             pending_files_clone.write().remove(&pending.id);
             
             if result.success {
-                let record = MoveRecord {
-                    id: Uuid::new_v4().to_string(),
-                    original_path: result.source,
-                    new_path: result.destination,
-                    rule_name: pending.rule_name,
-                    timestamp: Utc::now(),
-                    file_size: pending.file_size,
-                    can_undo: true,
-                };
-                history_clone.add(record);
-                log::info!("Moved file: {}", pending.file_name);
+                history_clone.add(move_record(&pending, &result, kind));
+                match result.status {
+                    MoveStatus::Deduplicated => log::info!("Dropped duplicate file: {}", pending.file_name),
+                    MoveStatus::CopiedSourceRemains => {
+                        log::warn!("Copied file: {} - {:?}", pending.file_name, result.error)
+                    }
+                    MoveStatus::Copied => log::info!("Copied file: {}", pending.file_name),
+                    MoveStatus::Hardlinked | MoveStatus::Symlinked => log::info!("Linked file: {}", pending.file_name),
+                    MoveStatus::Trashed => log::info!("Moved file to trash: {}", pending.file_name),
+                    MoveStatus::Deleted => log::info!("Deleted file: {}", pending.file_name),
+                    MoveStatus::Extracted => log::info!("Extracted archive: {}", pending.file_name),
+                    _ => log::info!("Moved file: {}", pending.file_name),
+                }
                 true
+            } else if result.status == MoveStatus::AlreadyMirrored {
+                log::debug!("Already copied or linked: {}", pending.file_name);
+                false
+            } else if result.status == MoveStatus::AlreadyInPlace {
+                log::debug!("Already in its destination: {}", pending.file_name);
+                false
             } else {
                 log::error!("Failed to move file: {} - {:?}", pending.file_name, result.error);
                 false
@@ -391,3 +1134,143 @@ fn process_pending_files(
         let _ = crate::config::save_config(&to_save);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::schema::{default_rules, Schedule};
+    use crate::engine::RecordKind;
+    use std::fs;
+
+    fn stored(path: &Path, schedule_id: Option<&str>) -> StoredPending {
+        StoredPending {
+            id: Uuid::new_v4().to_string(),
+            profile_id: AppConfig::DEFAULT_PROFILE_ID.to_string(),
+            path: path.to_path_buf(),
+            rule_name: "Documents".to_string(),
+            added_at: 100,
+            move_at: 130,
+            batch_id: "batch".to_string(),
+            schedule_id: schedule_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_conflict_waits_for_a_decision() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        let config = AppConfig {
+            watch_folder: root.join("in"),
+            destination_root: root.join("out"),
+            conflict_resolution: ConflictResolution::Ask,
+            ..Default::default()
+        };
+        let profile = config.default_profile();
+        let rule = SortRule::new("Documents", vec!["pdf"], "Documents", 10);
+        let settings = ActionSettings::from_config(&config);
+        fs::create_dir_all(root.join("in")).unwrap();
+        fs::create_dir_all(root.join("out/Documents")).unwrap();
+        fs::write(root.join("out/Documents/report.pdf"), b"existing").unwrap();
+        
+        let conflicting = |name: &str| {
+            let path = root.join("in").join(name);
+            fs::write(&path, b"incoming file").unwrap();
+            let mut pending = new_pending_file(&profile, &path, &rule, "batch").unwrap();
+            pending.target_name = "report.pdf".to_string();
+            let (result, _) = run_action(&pending, &profile.destination_root, &ConflictResolution::Ask, &settings);
+            assert_eq!(result.status, MoveStatus::NeedsDecision);
+            mark_needs_decision(&mut pending, &result.destination);
+            pending
+        };
+        let resolve = |mut pending: PendingFile, choice| {
+            let policy = apply_choice(&mut pending, choice)?;
+            assert_eq!((pending.state, pending.conflict.is_none()), (PendingState::Waiting, true));
+            let (result, _) = run_action(&pending, &profile.destination_root, &policy, &settings);
+            Some(result)
+        };
+        
+        let pending = conflicting("skip.pdf");
+        assert_eq!(pending.state, PendingState::NeedsDecision);
+        let conflict = pending.conflict.as_ref().unwrap();
+        assert_eq!((conflict.existing_size, conflict.incoming_size), (8, 13));
+        assert!(resolve(pending, ConflictChoice::Skip).is_none());
+        assert!(root.join("in/skip.pdf").exists());
+        
+        let kept = resolve(conflicting("both.pdf"), ConflictChoice::KeepBoth).unwrap();
+        assert_eq!(kept.destination, root.join("out/Documents/report (1).pdf"));
+        assert_eq!(fs::read(root.join("out/Documents/report.pdf")).unwrap(), b"existing");
+        
+        let renamed = resolve(conflicting("renamed.pdf"), ConflictChoice::Rename("notes.pdf".to_string())).unwrap();
+        assert_eq!(renamed.destination, root.join("out/Documents/notes.pdf"));
+        
+        let replaced = resolve(conflicting("replace.pdf"), ConflictChoice::Overwrite).unwrap();
+        assert_eq!(replaced.destination, root.join("out/Documents/report.pdf"));
+        assert_eq!(fs::read(root.join("out/Documents/report.pdf")).unwrap(), b"incoming file");
+        assert!(!root.join("in/replace.pdf").exists());
+    }
+
+    #[test]
+    fn test_restore_pending_queue() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        let mut unpack = SortRule::new("Unpack", vec!["zip"], "Unpacked", 200);
+        unpack.action = RuleAction::Extract;
+        let mut rules = default_rules();
+        rules.push(unpack);
+        let config = AppConfig {
+            watch_folder: root.join("in"),
+            destination_root: root.join("out"),
+            rules,
+            schedules: vec![Schedule {
+                id: "sweep".to_string(),
+                name: "Sweep".to_string(),
+                enabled: true,
+                cron: "0 3 * * *".to_string(),
+                folder: PathBuf::from("Inbox"),
+                rules: vec!["Documents".to_string()],
+                profile_id: None,
+                recursive: Default::default(),
+            }],
+            ..Default::default()
+        };
+        fs::create_dir_all(root.join("in")).unwrap();
+        fs::create_dir_all(root.join("out/Inbox")).unwrap();
+        for file in ["in/report.pdf", "in/data.zip", "out/Inbox/notes.pdf"] {
+            fs::write(root.join(file), b"contents").unwrap();
+        }
+
+        let history = History::at(root.join("history.json"), 10);
+        history.add(MoveRecord {
+            id: "extracted".to_string(),
+            original_path: root.join("in/data.zip"),
+            new_path: root.join("out/Unpacked/data"),
+            rule_name: "Unpack".to_string(),
+            timestamp: Utc::now(),
+            file_size: 8,
+            can_undo: true,
+            original_name: "data.zip".to_string(),
+            new_name: "data".to_string(),
+            kind: RecordKind::Extracted { archive_moved_to: None, archive_trashed: false },
+            batch_id: None,
+            modified: None,
+            profile_id: None,
+        });
+
+        let saved = vec![
+            stored(&root.join("in/report.pdf"), None),
+            stored(&root.join("out/Inbox/notes.pdf"), Some("sweep")),
+            stored(&root.join("in/data.zip"), None),
+            stored(&root.join("in/gone.pdf"), None),
+            stored(&root.join("out/Inbox/notes.pdf"), Some("removed")),
+        ];
+        let pending_files = Arc::new(RwLock::new(HashMap::new()));
+        assert_eq!(restore_pending(&pending_files, &history, &config, &saved), 2);
+
+        let files = pending_files.read();
+        let report = &files[&saved[0].id];
+        assert_eq!((report.move_at, report.destination.as_str()), (130, "Documents"));
+        let notes = &files[&saved[1].id];
+        assert_eq!(notes.schedule_id.as_deref(), Some("sweep"));
+        assert_eq!(StoredPending::from(notes), saved[1]);
+    }
+}
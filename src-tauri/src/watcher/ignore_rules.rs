@@ -0,0 +1,137 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::config::schema::WatchProfile;
+
+/// Name of the file in a watch folder whose gitignore-style patterns are added to the
+/// profile's own ignore patterns.
+pub const IGNORE_FILE_NAME: &str = ".autosortignore";
+
+/// Parsed rules per profile id, so watcher events don't re-read `.autosortignore`.
+static CACHE: Mutex<BTreeMap<String, CachedRules>> = Mutex::new(BTreeMap::new());
+
+struct CachedRules {
+    watch_folder: PathBuf,
+    patterns: Vec<String>,
+    /// Length and modification time of the ignore file when it was read
+    ignore_file: Option<(u64, SystemTime)>,
+    rules: IgnoreRules,
+}
+
+/// Gitignore-style matcher for files the watcher should never touch.
+#[derive(Clone)]
+pub struct IgnoreRules {
+    matcher: Arc<Gitignore>,
+}
+
+impl IgnoreRules {
+    /// The profile's rules, parsed again only when its patterns or watch folder changed
+    /// or its `.autosortignore` was edited since they were last built.
+    pub fn for_profile(profile: &WatchProfile) -> Self {
+        let ignore_file = ignore_file_state(&profile.watch_folder);
+        let mut cache = CACHE.lock();
+        if let Some(cached) = cache.get(&profile.id) {
+            if cached.watch_folder == profile.watch_folder
+                && cached.patterns == profile.ignore_patterns
+                && cached.ignore_file == ignore_file
+            {
+                return cached.rules.clone();
+            }
+        }
+
+        let rules = Self::from_profile(profile);
+        cache.insert(profile.id.clone(), CachedRules {
+            watch_folder: profile.watch_folder.clone(),
+            patterns: profile.ignore_patterns.clone(),
+            ignore_file,
+            rules: rules.clone(),
+        });
+        rules
+    }
+
+    /// Forget every cached profile, e.g. after the config is saved.
+    pub fn clear_cache() {
+        CACHE.lock().clear();
+    }
+
+    /// Build the matcher from the profile's patterns plus the watch folder's
+    /// `.autosortignore`, if there is one. Invalid patterns are logged and skipped.
+    fn from_profile(profile: &WatchProfile) -> Self {
+        let mut builder = GitignoreBuilder::new(&profile.watch_folder);
+        if let Err(e) = builder.case_insensitive(cfg!(windows)) {
+            log::warn!("Failed to configure ignore patterns: {}", e);
+        }
+
+        for pattern in &profile.ignore_patterns {
+            if let Err(e) = builder.add_line(None, pattern) {
+                log::warn!("Invalid ignore pattern \"{}\": {}", pattern, e);
+            }
+        }
+
+        let ignore_file = profile.watch_folder.join(IGNORE_FILE_NAME);
+        if ignore_file.is_file() {
+            if let Some(e) = builder.add(&ignore_file) {
+                log::warn!("Problem reading {:?}: {}", ignore_file, e);
+            }
+        }
+
+        let matcher = builder.build().unwrap_or_else(|e| {
+            log::warn!("Failed to build ignore patterns: {}", e);
+            Gitignore::empty()
+        });
+        Self { matcher: Arc::new(matcher) }
+    }
+
+    /// Whether `path` itself matches; callers check parent folders as they walk.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.matcher.matched(path, is_dir).is_ignore()
+    }
+}
+
+fn ignore_file_state(watch_folder: &Path) -> Option<(u64, SystemTime)> {
+    let meta = fs::metadata(watch_folder.join(IGNORE_FILE_NAME)).ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+/// Check patterns before they are saved.
+pub fn validate_ignore_patterns(patterns: &[String]) -> Result<(), String> {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        builder.add_line(None, pattern)
+            .map_err(|e| format!("Invalid ignore pattern \"{}\": {}", pattern, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::schema::Config;
+
+    #[test]
+    fn test_cached_rules_follow_the_ignore_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        let mut profile = Config { watch_folder: root.clone(), ..Config::default() }.default_profile();
+        profile.id = "ignore-cache-test".to_string();
+        let report = root.join("report.pdf");
+
+        assert!(!IgnoreRules::for_profile(&profile).is_ignored(&report, false));
+
+        fs::write(root.join(IGNORE_FILE_NAME), "*.pdf\n").unwrap();
+        assert!(IgnoreRules::for_profile(&profile).is_ignored(&report, false));
+
+        fs::write(root.join(IGNORE_FILE_NAME), "*.docx\n").unwrap();
+        let mtime = filetime::FileTime::from_unix_time(1_600_000_000, 0);
+        filetime::set_file_mtime(root.join(IGNORE_FILE_NAME), mtime).unwrap();
+        assert!(!IgnoreRules::for_profile(&profile).is_ignored(&report, false));
+
+        profile.ignore_patterns.push("report.*".to_string());
+        assert!(IgnoreRules::for_profile(&profile).is_ignored(&report, false));
+    }
+}
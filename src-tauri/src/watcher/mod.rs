@@ -1,3 +1,13 @@
 pub mod handler;
+pub mod ignore_rules;
+pub mod pending_store;
+pub mod scheduler;
+pub mod scope;
+pub mod stability;
 
 pub use handler::*;
+pub use ignore_rules::*;
+pub use pending_store::*;
+pub use scheduler::*;
+pub use scope::*;
+pub use stability::*;
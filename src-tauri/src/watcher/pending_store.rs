@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::watcher::handler::PendingFile;
+
+/// The part of a pending file that survives a restart. Everything else is worked out
+/// again from the file and the current rules when the queue is restored.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredPending {
+    pub id: String,
+    pub profile_id: String,
+    pub path: PathBuf,
+    pub rule_name: String,
+    pub added_at: i64,
+    pub move_at: i64,
+    #[serde(default)]
+    pub batch_id: String,
+    #[serde(default)]
+    pub schedule_id: Option<String>,
+}
+
+impl From<&PendingFile> for StoredPending {
+    fn from(pending: &PendingFile) -> Self {
+        Self {
+            id: pending.id.clone(),
+            profile_id: pending.profile_id.clone(),
+            path: pending.path.clone(),
+            rule_name: pending.rule_name.clone(),
+            added_at: pending.added_at,
+            move_at: pending.move_at,
+            batch_id: pending.batch_id.clone(),
+            schedule_id: pending.schedule_id.clone(),
+        }
+    }
+}
+
+fn get_pending_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("autosort");
+    fs::create_dir_all(&config_dir).ok();
+    config_dir.join("pending.json")
+}
+
+pub fn load_pending() -> Vec<StoredPending> {
+    read_pending(&get_pending_path())
+}
+
+fn read_pending(path: &Path) -> Vec<StoredPending> {
+    if !path.exists() {
+        return Vec::new();
+    }
+    
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::error!("Failed to parse pending queue: {}", e);
+            Vec::new()
+        }),
+        Err(e) => {
+            log::error!("Failed to read pending queue: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn save_pending(path: &Path, entries: &[StoredPending]) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(entries)
+        .map_err(|e| format!("Failed to serialize pending queue: {}", e))?;
+    
+    // Write to a temporary file first so a crash mid-write can't leave a truncated queue
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, content)
+        .and_then(|_| fs::rename(&temp, path))
+        .map_err(|e| format!("Failed to write pending queue: {}", e))
+}
+
+/// Keeps `pending.json` in step with the in-memory queue, writing only when it changed.
+pub struct PendingStore {
+    last_saved: Vec<StoredPending>,
+}
+
+impl PendingStore {
+    pub fn new(saved: Vec<StoredPending>) -> Self {
+        Self { last_saved: saved }
+    }
+    
+    pub fn sync(&mut self, pending_files: &HashMap<String, PendingFile>) {
+        let mut entries: Vec<StoredPending> = pending_files.values().map(StoredPending::from).collect();
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+        
+        if entries == self.last_saved {
+            return;
+        }
+        match save_pending(&get_pending_path(), &entries) {
+            Ok(()) => self.last_saved = entries,
+            Err(e) => log::error!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_queue_round_trip() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("pending.json");
+        assert!(read_pending(&path).is_empty());
+
+        let entries = vec![
+            StoredPending {
+                id: "a".to_string(),
+                profile_id: "default".to_string(),
+                path: PathBuf::from("/downloads/report.pdf"),
+                rule_name: "Documents".to_string(),
+                added_at: 100,
+                move_at: 130,
+                batch_id: "session".to_string(),
+                schedule_id: None,
+            },
+            StoredPending {
+                id: "b".to_string(),
+                profile_id: "default".to_string(),
+                path: PathBuf::from("/sorted/Inbox/notes.pdf"),
+                rule_name: "Documents".to_string(),
+                added_at: 200,
+                move_at: 200,
+                batch_id: "sweep-run".to_string(),
+                schedule_id: Some("sweep".to_string()),
+            },
+        ];
+        save_pending(&path, &entries).unwrap();
+        assert_eq!(read_pending(&path), entries);
+
+        // Queues saved before batches and schedules existed still load
+        fs::write(&path, r#"[{"id": "c", "profile_id": "default", "path": "/downloads/a.zip",
+            "rule_name": "Archives", "added_at": 1, "move_at": 2}]"#).unwrap();
+        let old = read_pending(&path);
+        assert_eq!((old[0].batch_id.as_str(), old[0].schedule_id.as_ref()), ("", None));
+    }
+}
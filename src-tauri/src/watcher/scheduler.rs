@@ -0,0 +1,321 @@
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::config::schema::{Config as AppConfig, Schedule, WatchProfile};
+
+/// What happened the last time a schedule ran.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScheduleRun {
+    pub last_run: Option<DateTime<Utc>>,
+    /// Files the last run queued for sorting
+    #[serde(default)]
+    pub queued: usize,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// A schedule with its last and next run, as reported to the UI.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleStatus {
+    pub id: String,
+    pub name: String,
+    pub enabled: bool,
+    pub cron: String,
+    pub folder: PathBuf,
+    pub last_run: Option<DateTime<Utc>>,
+    /// `None` when the schedule is disabled or its cron expression is invalid
+    pub next_run: Option<DateTime<Utc>>,
+    pub queued: usize,
+    pub error: Option<String>,
+}
+
+/// Tracks when each schedule last ran, persisted in `schedules.json` so runs missed while
+/// the app was closed happen once it starts again.
+pub struct Scheduler {
+    runs: HashMap<String, ScheduleRun>,
+    /// Schedules that have never run count from here, so they don't all fire at startup
+    started_at: DateTime<Utc>,
+}
+
+impl Scheduler {
+    pub fn load() -> Self {
+        Self {
+            runs: load_runs(),
+            started_at: Utc::now(),
+        }
+    }
+
+    /// When `schedule` should run next, which is in the past if a run is overdue.
+    pub fn next_run(&self, schedule: &Schedule) -> Option<DateTime<Utc>> {
+        if !schedule.enabled {
+            return None;
+        }
+        let from = self.runs.get(&schedule.id)
+            .and_then(|r| r.last_run)
+            .unwrap_or(self.started_at);
+        parse_cron(&schedule.cron).ok()?
+            .after(&from.with_timezone(&Local))
+            .next()
+            .map(|t| t.with_timezone(&Utc))
+    }
+
+    pub fn due(&self, schedules: &[Schedule], now: DateTime<Utc>) -> Vec<Schedule> {
+        schedules.iter()
+            .filter(|s| self.next_run(s).is_some_and(|next| next <= now))
+            .cloned()
+            .collect()
+    }
+
+    /// Note that `id` ran at `at`, having queued that many files or failed.
+    pub fn record_run(&mut self, id: &str, at: DateTime<Utc>, outcome: Result<usize, String>) {
+        let run = ScheduleRun {
+            last_run: Some(at),
+            queued: *outcome.as_ref().unwrap_or(&0),
+            error: outcome.err(),
+        };
+        self.runs.insert(id.to_string(), run);
+        if let Err(e) = save_runs(&self.runs) {
+            log::error!("{}", e);
+        }
+    }
+
+    pub fn statuses(&self, config: &AppConfig) -> Vec<ScheduleStatus> {
+        config.schedules.iter()
+            .map(|schedule| {
+                let run = self.runs.get(&schedule.id).cloned().unwrap_or_default();
+                let invalid = parse_cron(&schedule.cron).err();
+                ScheduleStatus {
+                    id: schedule.id.clone(),
+                    name: schedule.name.clone(),
+                    enabled: schedule.enabled,
+                    cron: schedule.cron.clone(),
+                    folder: schedule.folder.clone(),
+                    last_run: run.last_run,
+                    next_run: self.next_run(schedule),
+                    queued: run.queued,
+                    error: invalid.or(run.error),
+                }
+            })
+            .collect()
+    }
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// Parse a cron expression. The usual five fields are accepted as well as the six or
+/// seven (with seconds, and optionally years) of the `cron` crate.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule, String> {
+    let expression = expression.trim();
+    let fields: Vec<&str> = expression.split_whitespace().collect();
+    let full = match fields.as_slice() {
+        [minute, hour, day, month, weekday] => weekday_names(weekday)
+            .map(|weekday| format!("0 {} {} {} {} {}", minute, hour, day, month, weekday)),
+        _ => Ok(expression.to_string()),
+    };
+    full.and_then(|full| cron::Schedule::from_str(&full).map_err(|e| e.to_string()))
+        .map_err(|e| format!("Invalid cron expression \"{}\": {}", expression, e))
+}
+
+/// Five-field cron numbers weekdays from 0 (or 7) for Sunday but the `cron` crate from 1,
+/// so numbered weekdays are written out as names, e.g. `1-5` as `Mon,Tue,Wed,Thu,Fri`.
+fn weekday_names(field: &str) -> Result<String, String> {
+    let number = |s: &str| s.parse::<usize>().ok().filter(|n| *n <= 7)
+        .ok_or_else(|| format!("Invalid day of week: {}", s));
+    let items = field.split(',').map(|item| {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, Some(step)),
+            None => (item, None),
+        };
+        if !range.contains(|c: char| c.is_ascii_digit()) {
+            return Ok(item.to_string());
+        }
+        let (from, to) = match range.split_once('-') {
+            Some((from, to)) => (number(from)?, number(to)?),
+            None if step.is_some() => (number(range)?, 7),
+            None => (number(range)?, number(range)?),
+        };
+        let step = match step {
+            Some(step) => step.parse::<usize>().ok().filter(|s| *s > 0)
+                .ok_or_else(|| format!("Invalid step: {}", step))?,
+            None => 1,
+        };
+        if from > to {
+            return Err(format!("Invalid day of week range: {}", range));
+        }
+        let days: BTreeSet<usize> = (from..=to).step_by(step).map(|day| day % 7).collect();
+        Ok(days.into_iter().map(|day| WEEKDAYS[day]).collect::<Vec<_>>().join(","))
+    });
+    items.collect::<Result<Vec<_>, String>>().map(|items| items.join(","))
+}
+
+/// The profile a schedule runs as: its own profile narrowed to the schedule's folder and
+/// rules, without a grace period.
+pub fn schedule_profile(config: &AppConfig, schedule: &Schedule) -> Result<WatchProfile, String> {
+    let mut profile = match &schedule.profile_id {
+        Some(id) => config.profile(id).ok_or_else(|| format!("Profile {} not found", id))?,
+        None => config.default_profile(),
+    };
+
+    let rules: Vec<_> = profile.rules.iter()
+        .filter(|r| schedule.rules.iter().any(|wanted| *wanted == r.id || *wanted == r.name))
+        .map(|r| {
+            let mut rule = r.clone();
+            rule.enabled = true;
+            rule
+        })
+        .collect();
+    if rules.is_empty() {
+        return Err("None of the schedule's rules exist".to_string());
+    }
+
+    profile.watch_folder = profile.destination_root.join(&schedule.folder);
+    profile.rules = rules;
+    profile.grace_period_seconds = 0;
+    profile.recursive = schedule.recursive.clone();
+    Ok(profile)
+}
+
+pub fn validate_schedule(schedule: &Schedule, config: &AppConfig) -> Result<(), String> {
+    let check = || {
+        parse_cron(&schedule.cron)?;
+        if schedule.folder.as_os_str().is_empty() {
+            return Err("Choose a folder to sweep".to_string());
+        }
+        schedule_profile(config, schedule).map(|_| ())
+    };
+    check().map_err(|e| format!("Schedule \"{}\": {}", schedule.name, e))
+}
+
+fn get_runs_path() -> PathBuf {
+    let config_dir = dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("autosort");
+    fs::create_dir_all(&config_dir).ok();
+    config_dir.join("schedules.json")
+}
+
+fn load_runs() -> HashMap<String, ScheduleRun> {
+    let path = get_runs_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log::error!("Failed to parse schedule runs: {}", e);
+            HashMap::new()
+        }),
+        Err(e) => {
+            log::error!("Failed to read schedule runs: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+fn save_runs(runs: &HashMap<String, ScheduleRun>) -> Result<(), String> {
+    let path = get_runs_path();
+    let content = serde_json::to_string_pretty(runs)
+        .map_err(|e| format!("Failed to serialize schedule runs: {}", e))?;
+
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, content)
+        .and_then(|_| fs::rename(&temp, &path))
+        .map_err(|e| format!("Failed to write schedule runs: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, TimeZone, Timelike, Weekday};
+
+    #[test]
+    fn test_next_run_follows_last_run() {
+        assert!(parse_cron("0 3 * * Sun").is_ok());
+        assert!(parse_cron("0 0 3 * * Sun *").is_ok());
+        assert!(parse_cron("every sunday").is_err());
+        assert!(parse_cron("0 3 * * 8").is_err());
+
+        // Numbered weekdays count from 0 for Sunday, as in standard cron
+        let from = Local.with_ymd_and_hms(2024, 6, 5, 12, 0, 0).unwrap();
+        let weekdays = |cron: &str, count: usize| -> Vec<Weekday> {
+            parse_cron(cron).unwrap().after(&from).take(count).map(|t| t.weekday()).collect()
+        };
+        assert_eq!(weekdays("0 3 * * 0", 2), vec![Weekday::Sun, Weekday::Sun]);
+        assert_eq!(weekdays("0 3 * * 7", 1), vec![Weekday::Sun]);
+        assert_eq!(
+            weekdays("0 3 * * 1-5", 5),
+            vec![Weekday::Thu, Weekday::Fri, Weekday::Mon, Weekday::Tue, Weekday::Wed],
+        );
+
+        let schedule = Schedule {
+            id: "weekly".to_string(),
+            name: "Weekly".to_string(),
+            enabled: true,
+            cron: "30 3 * * Sun".to_string(),
+            folder: PathBuf::from("Documents"),
+            rules: vec!["Documents".to_string()],
+            profile_id: None,
+            recursive: Default::default(),
+        };
+        let started_at = Local.with_ymd_and_hms(2024, 6, 5, 12, 0, 0).unwrap().with_timezone(&Utc);
+        let mut scheduler = Scheduler { runs: HashMap::new(), started_at };
+
+        let next = scheduler.next_run(&schedule).unwrap().with_timezone(&Local);
+        assert_eq!((next.weekday(), next.day(), next.hour(), next.minute()), (Weekday::Sun, 9, 3, 30));
+        assert_eq!(scheduler.due(std::slice::from_ref(&schedule), next.with_timezone(&Utc)).len(), 1);
+
+        // Later runs count from the last one, so one missed while the app was closed is
+        // overdue rather than skipped
+        let last_run = Local.with_ymd_and_hms(2024, 6, 16, 3, 30, 0).unwrap().with_timezone(&Utc);
+        scheduler.runs.insert(schedule.id.clone(), ScheduleRun { last_run: Some(last_run), ..Default::default() });
+        let next = scheduler.next_run(&schedule).unwrap().with_timezone(&Local);
+        assert_eq!(next.day(), 23);
+        let now = Local.with_ymd_and_hms(2024, 7, 1, 9, 0, 0).unwrap().with_timezone(&Utc);
+        assert_eq!(scheduler.due(std::slice::from_ref(&schedule), now).len(), 1);
+
+        scheduler.runs.clear();
+        let disabled = Schedule { enabled: false, ..schedule };
+        assert_eq!(scheduler.next_run(&disabled), None);
+    }
+
+    #[test]
+    fn test_sweeping_a_destination_leaves_its_files_alone() {
+        use crate::config::schema::ConflictResolution;
+        use crate::engine::mover::{move_file, MoveStatus};
+        use crate::engine::plan_moves;
+
+        let temp = tempfile::tempdir().unwrap();
+        let config = AppConfig { destination_root: temp.path().to_path_buf(), ..Default::default() };
+        let schedule = Schedule {
+            id: "sweep".to_string(),
+            name: "Sweep".to_string(),
+            enabled: true,
+            cron: "0 3 * * *".to_string(),
+            folder: PathBuf::from("Documents"),
+            rules: vec!["Documents".to_string()],
+            profile_id: None,
+            recursive: Default::default(),
+        };
+        let profile = schedule_profile(&config, &schedule).unwrap();
+        fs::create_dir_all(&profile.watch_folder).unwrap();
+        let file = profile.watch_folder.join("a.pdf");
+        fs::write(&file, b"only copy").unwrap();
+
+        for policy in [ConflictResolution::Deduplicate { duplicates_folder: None }, ConflictResolution::Rename] {
+            let files = vec![(file.clone(), 0)];
+            assert!(plan_moves(&files, &profile.rules, &profile.destination_root, &policy).is_empty());
+
+            let result = move_file(&file, &profile.destination_root, "Documents", "a.pdf", &policy, false);
+            assert_eq!(result.status, MoveStatus::AlreadyInPlace);
+        }
+        let names: Vec<_> = fs::read_dir(&profile.watch_folder).unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec!["a.pdf"]);
+        assert_eq!(fs::read(&file).unwrap(), b"only copy");
+    }
+}
@@ -0,0 +1,226 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::schema::{AfterExtract, RuleAction, SortRule, WatchProfile};
+use crate::engine::template::static_prefix;
+use crate::watcher::ignore_rules::IgnoreRules;
+
+/// Which files under the watch folder are eligible for sorting, and how deep they are.
+pub struct WatchScope {
+    root: PathBuf,
+    max_depth: usize,
+    excluded_dirs: Vec<PathBuf>,
+    excluded_names: Vec<String>,
+    ignore: IgnoreRules,
+}
+
+impl WatchScope {
+    pub fn from_profile(profile: &WatchProfile) -> Self {
+        let root = profile.watch_folder.clone();
+        let max_depth = if profile.recursive.enabled { profile.recursive.max_depth } else { 0 };
+
+        let mut excluded_dirs = Vec::new();
+        let mut excluded_names = Vec::new();
+
+        for entry in &profile.recursive.exclude {
+            let entry = entry.trim_matches(|c| c == '/' || c == '\\');
+            if entry.is_empty() {
+                continue;
+            }
+            if Path::new(entry).components().count() > 1 {
+                excluded_dirs.push(root.join(entry));
+            } else {
+                excluded_names.push(entry.to_string());
+            }
+        }
+
+        // Never descend into folders we sort into, or moved files would be picked up again.
+        // For templated destinations only the fixed part before the first placeholder is known.
+        for folder in profile.rules.iter().flat_map(sorted_into) {
+            let dest = profile.destination_root.join(static_prefix(folder));
+            if dest != root {
+                excluded_dirs.push(dest);
+            }
+        }
+
+        Self {
+            root,
+            max_depth,
+            excluded_dirs,
+            excluded_names,
+            ignore: IgnoreRules::for_profile(profile),
+        }
+    }
+
+    pub fn is_recursive(&self) -> bool {
+        self.max_depth > 0
+    }
+
+    /// Depth of a file below the watch folder (0 = directly inside it), or `None`
+    /// if the file is outside the scope or ignored.
+    pub fn depth_of(&self, path: &Path) -> Option<usize> {
+        let relative = path.strip_prefix(&self.root).ok()?;
+        let depth = relative.components().count().checked_sub(1)?;
+        if depth > self.max_depth || self.ignore.is_ignored(path, false) {
+            return None;
+        }
+
+        let mut dir = self.root.clone();
+        for component in relative.parent()?.components() {
+            let Component::Normal(name) = component else {
+                return None;
+            };
+            dir.push(name);
+            if self.is_excluded_dir(&dir) {
+                return None;
+            }
+        }
+
+        Some(depth)
+    }
+
+    /// All files within the scope, paired with their depth.
+    pub fn collect_files(&self) -> Vec<(PathBuf, usize)> {
+        let mut files = Vec::new();
+        self.walk(&self.root, 0, &mut files);
+        files.sort();
+        files
+    }
+
+    fn walk(&self, dir: &Path, depth: usize, files: &mut Vec<(PathBuf, usize)>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                log::warn!("Failed to read {:?}: {}", dir, e);
+                return;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            // Don't follow symlinks into other parts of the filesystem
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                if depth < self.max_depth && !self.is_excluded_dir(&path) {
+                    self.walk(&path, depth + 1, files);
+                }
+            } else if path.is_file() && !self.ignore.is_ignored(&path, false) {
+                files.push((path, depth));
+            }
+        }
+    }
+
+    fn is_excluded_dir(&self, dir: &Path) -> bool {
+        let name = dir.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+        self.ignore.is_ignored(dir, true)
+            || self.excluded_names.iter().any(|n| n == name)
+            || self.excluded_dirs.iter().any(|d| dir.starts_with(d))
+    }
+}
+
+/// Folders (relative to the destination root) that `rule` puts files in. Archives an
+/// `Extract` rule moves away, or that compaction creates, count too, since they would
+/// otherwise be sorted again.
+fn sorted_into(rule: &SortRule) -> Vec<&String> {
+    if !rule.action.uses_destination() {
+        return Vec::new();
+    }
+    let mut folders = vec![&rule.destination_folder];
+    if let (RuleAction::Extract, AfterExtract::MoveTo(folder)) = (rule.action, &rule.after_extract) {
+        folders.push(folder);
+    }
+    if let Some(compaction) = &rule.compaction {
+        folders.push(&compaction.archive_folder);
+    }
+    folders
+}
+
+/// Check that a recursive profile can keep the folders it sorts into out of its scan.
+/// A destination that starts with a placeholder, like "{ext}", lands directly in the
+/// watch folder when the destination root is the watch folder, so its subfolders can't
+/// be excluded and sorted files would be picked up again.
+pub fn validate_destinations(profile: &WatchProfile) -> Result<(), String> {
+    if !profile.recursive.enabled {
+        return Ok(());
+    }
+    for rule in &profile.rules {
+        for folder in sorted_into(rule) {
+            let dest = profile.destination_root.join(static_prefix(folder));
+            if folder.contains('{') && dest == profile.watch_folder {
+                return Err(format!(
+                    "Rule \"{}\": \"{}\" would sort files into subfolders of the watch folder, which are \
+                     scanned in recursive mode. Start it with a fixed folder, like \"Sorted/{}\".",
+                    rule.name, folder, folder
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::schema::Config as AppConfig;
+    use std::fs;
+
+    #[test]
+    fn test_recursive_scan_skips_destination_folders() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        fs::create_dir_all(root.join("sub/deeper")).unwrap();
+        fs::create_dir_all(root.join("Images")).unwrap();
+        fs::write(root.join("top.png"), b"").unwrap();
+        fs::write(root.join("sub/nested.png"), b"").unwrap();
+        fs::write(root.join("sub/deeper/too-deep.png"), b"").unwrap();
+        fs::write(root.join("Images/sorted.png"), b"").unwrap();
+
+        let mut config = AppConfig {
+            watch_folder: root.clone(),
+            destination_root: root.clone(),
+            ..AppConfig::default()
+        };
+        config.recursive.enabled = true;
+        config.recursive.max_depth = 1;
+
+        let scope = WatchScope::from_profile(&config.default_profile());
+        let files = scope.collect_files();
+
+        assert_eq!(files, vec![(root.join("sub/nested.png"), 1), (root.join("top.png"), 0)]);
+        assert_eq!(scope.depth_of(&root.join("Images/sorted.png")), None);
+        assert!(validate_destinations(&config.default_profile()).is_ok());
+
+        config.rules[0].destination_folder = "{ext}".to_string();
+        assert!(validate_destinations(&config.default_profile()).is_err());
+        config.rules[0].destination_folder = "Sorted/{ext}".to_string();
+        assert!(validate_destinations(&config.default_profile()).is_ok());
+    }
+
+    #[test]
+    fn test_ignore_patterns_and_ignore_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        fs::create_dir_all(root.join("keep")).unwrap();
+        for name in ["movie.mkv.part", "~$report.docx", "song.mp3.aria2", ".hidden", "notes.txt", "report.docx"] {
+            fs::write(root.join(name), b"").unwrap();
+        }
+        fs::write(root.join("keep/notes.txt"), b"").unwrap();
+        fs::write(root.join(".autosortignore"), "*.txt\n!keep/notes.txt\n").unwrap();
+
+        let mut config = AppConfig {
+            watch_folder: root.clone(),
+            destination_root: root.join("Sorted"),
+            ..AppConfig::default()
+        };
+        config.recursive.enabled = true;
+
+        let scope = WatchScope::from_profile(&config.default_profile());
+        let files: Vec<PathBuf> = scope.collect_files().into_iter().map(|(p, _)| p).collect();
+
+        assert_eq!(files, vec![root.join("keep/notes.txt"), root.join("report.docx")]);
+        assert_eq!(scope.depth_of(&root.join("movie.mkv.part")), None);
+    }
+}
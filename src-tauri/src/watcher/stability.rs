@@ -0,0 +1,118 @@
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::watcher::handler::{PendingFile, PendingState};
+
+/// Size and modification time of a file at one poll.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSnapshot {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl FileSnapshot {
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = path.metadata().ok()?;
+        Some(Self {
+            size: meta.len(),
+            modified: meta.modified().ok(),
+        })
+    }
+}
+
+/// Record one poll of a pending file whose grace period has passed. Returns `true` once
+/// its size and mtime have been unchanged for `required_checks` polls in a row and no
+/// other process has it open; until then the file is marked `WaitingForStable`.
+pub fn poll_stability(pending: &mut PendingFile, required_checks: u32) -> bool {
+    let current = FileSnapshot::of(&pending.path);
+
+    if current.is_some() && current == pending.last_seen {
+        pending.stable_checks = pending.stable_checks.saturating_add(1);
+    } else {
+        pending.stable_checks = 0;
+        if let Some(snapshot) = &current {
+            pending.file_size = snapshot.size;
+        }
+        pending.last_seen = current;
+    }
+
+    let stable = pending.stable_checks >= required_checks && can_open_exclusively(&pending.path);
+    pending.state = if stable { PendingState::Waiting } else { PendingState::WaitingForStable };
+    stable
+}
+
+/// Whether the file can be opened with no sharing, i.e. nothing else is writing to it.
+/// Only Windows enforces this; elsewhere the size/mtime checks have to do.
+#[cfg(windows)]
+pub fn can_open_exclusively(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .read(true)
+        .share_mode(0)
+        .open(path)
+        .is_ok()
+}
+
+#[cfg(not(windows))]
+pub fn can_open_exclusively(_path: &Path) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::schema::{AfterExtract, RuleAction};
+    use std::fs;
+    use std::io::Write;
+
+    fn pending(path: &Path) -> PendingFile {
+        PendingFile {
+            id: "pending".to_string(),
+            profile_id: "default".to_string(),
+            path: path.to_path_buf(),
+            file_name: "movie.mkv".to_string(),
+            target_name: "movie.mkv".to_string(),
+            destination: "Videos".to_string(),
+            rule_name: "Videos".to_string(),
+            action: RuleAction::Move,
+            after_extract: AfterExtract::Keep,
+            added_at: 0,
+            move_at: 0,
+            file_size: 0,
+            state: PendingState::Waiting,
+            conflict: None,
+            stable_checks: 0,
+            batch_id: String::new(),
+            schedule_id: None,
+            last_seen: None,
+        }
+    }
+
+    #[test]
+    fn test_growing_file_waits_until_it_settles() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("movie.mkv");
+        fs::write(&path, b"frames").unwrap();
+        let mut pending = pending(&path);
+
+        // Still being downloaded: every poll sees a new size
+        for _ in 0..3 {
+            fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"more").unwrap();
+            assert!(!poll_stability(&mut pending, 2));
+            assert_eq!((pending.stable_checks, pending.state), (0, PendingState::WaitingForStable));
+        }
+        assert_eq!(pending.file_size, 18);
+
+        // Done: it goes once it has been unchanged for the required polls
+        assert!(!poll_stability(&mut pending, 2));
+        assert_eq!(pending.stable_checks, 1);
+        assert!(poll_stability(&mut pending, 2));
+        assert_eq!(pending.state, PendingState::Waiting);
+
+        // A file that disappears is never stable
+        fs::remove_file(&path).unwrap();
+        assert!(!poll_stability(&mut pending, 2));
+        assert_eq!(pending.stable_checks, 0);
+    }
+}
@@ -3,9 +3,19 @@ import type {
   Config,
   SortRule,
   PendingFile,
+  PlannedMove,
+  CompactionSummary,
+  ConflictChoice,
+  ConflictOutcome,
+  DuplicateGroup,
+  DuplicateAction,
+  DuplicateOutcome,
   MoveRecord,
+  UndoSelection,
+  UndoOutcome,
   HistoryStats,
   WatcherStatus,
+  ScheduleStatus,
 } from "./types";
 
 // Config commands
@@ -19,29 +29,63 @@ export const stopWatcher = () => invoke<void>("stop_watcher");
 export const pauseWatcher = () => invoke<void>("pause_watcher");
 export const resumeWatcher = () => invoke<void>("resume_watcher");
 export const getWatcherStatus = () => invoke<WatcherStatus>("get_watcher_status");
+export const getSchedules = () => invoke<ScheduleStatus[]>("get_schedules");
 
 // Pending files commands
-export const getPendingFiles = () => invoke<PendingFile[]>("get_pending_files");
+export const getPendingFiles = (profileId?: string) =>
+  invoke<PendingFile[]>("get_pending_files", { profileId });
 export const cancelPendingFile = (id: string) =>
   invoke<boolean>("cancel_pending_file", { id });
 export const moveFileNow = (id: string) => invoke<void>("move_file_now", { id });
-export const scanFolder = () => invoke<PendingFile[]>("scan_folder");
+export const resolveConflict = (id: string, choice: ConflictChoice, applyToAll = false) =>
+  invoke<ConflictOutcome[]>("resolve_conflict", { id, choice, applyToAll });
+/** Emitted with a WatcherEvent when a move is waiting for a conflict decision */
+export const CONFLICT_DETECTED_EVENT = "conflict-detected";
+export const scanFolder = (profileId?: string) =>
+  invoke<PendingFile[]>("scan_folder", { profileId });
+export const previewScan = (profileId?: string) =>
+  invoke<PlannedMove[]>("preview_scan", { profileId });
+export const compactNow = (profileId?: string) =>
+  invoke<CompactionSummary>("compact_now", { profileId });
+
+// Duplicate commands
+export const findDuplicates = (profileId?: string) =>
+  invoke<DuplicateGroup[]>("find_duplicates", { profileId });
+export const resolveDuplicates = (
+  groups: DuplicateGroup[],
+  action: DuplicateAction,
+  profileId?: string
+) =>
+  invoke<DuplicateOutcome[]>("resolve_duplicates", { groups, action, profileId });
 
 // History commands
 export const getHistory = () => invoke<MoveRecord[]>("get_history");
 export const getRecentHistory = (count: number) =>
   invoke<MoveRecord[]>("get_recent_history", { count });
 export const getHistoryStats = () => invoke<HistoryStats>("get_history_stats");
-export const undoFileMove = (id: string) => invoke<void>("undo_file_move", { id });
+export const undoFileMove = (id: string, force?: boolean) =>
+  invoke<void>("undo_file_move", { id, force });
+/** Warning to confirm before undoing, if the file changed after it was moved */
+export const checkUndo = (id: string) => invoke<string | null>("check_undo", { id });
+export const undoMoves = (selection: UndoSelection, force?: boolean) =>
+  invoke<UndoOutcome[]>("undo_moves", { selection, force });
+export const redoFileMove = (id: string) =>
+  invoke<MoveRecord>("redo_file_move", { id });
+export const undoLast = (force?: boolean) => invoke<MoveRecord>("undo_last", { force });
+export const redoLast = () => invoke<MoveRecord>("redo_last");
 export const clearHistory = () => invoke<void>("clear_history");
 
-// Rules commands
-export const getRules = () => invoke<SortRule[]>("get_rules");
-export const addRule = (rule: SortRule) => invoke<void>("add_rule", { rule });
-export const updateRule = (rule: SortRule) => invoke<void>("update_rule", { rule });
-export const deleteRule = (id: string) => invoke<void>("delete_rule", { id });
-export const reorderRules = (ruleIds: string[]) =>
-  invoke<void>("reorder_rules", { ruleIds });
+// Rules commands (profileId defaults to the default profile)
+export const getRules = (profileId?: string) =>
+  invoke<SortRule[]>("get_rules", { profileId });
+export const addRule = (rule: SortRule, profileId?: string) =>
+  invoke<void>("add_rule", { rule, profileId });
+export const updateRule = (rule: SortRule, profileId?: string) =>
+  invoke<void>("update_rule", { rule, profileId });
+export const deleteRule = (id: string, profileId?: string) =>
+  invoke<void>("delete_rule", { id, profileId });
+export const reorderRules = (ruleIds: string[], profileId?: string) =>
+  invoke<void>("reorder_rules", { ruleIds, profileId });
 export const testRule = (filename: string, rules: SortRule[]) =>
   invoke<string | null>("test_rule", { filename, rules });
 
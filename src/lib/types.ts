@@ -11,6 +11,81 @@ export interface Config {
   history_limit: number;
   /** All-time count of files moved (persisted) */
   total_files_moved?: number;
+  /** Also sort files in subfolders of the watch folder */
+  recursive?: RecursiveOptions;
+  /** Extra watch folders; the top-level settings form the default profile */
+  profiles?: WatchProfile[];
+  /** Polls a file must stay unchanged after the grace period before it moves */
+  stable_checks?: number;
+  /** Gitignore-style patterns for files that are never sorted */
+  ignore_patterns?: string[];
+  /** Hash-check copies made when moving between drives */
+  verify_copies?: boolean;
+  /** What undo does when the original location is taken */
+  undo_conflict?: UndoConflict;
+  extract_limits?: ExtractLimits;
+  /** Recurring sweeps of a folder with some of a profile's rules */
+  schedules?: Schedule[];
+}
+
+export interface Schedule {
+  id: string;
+  name: string;
+  enabled: boolean;
+  /** Cron expression in local time, e.g. "0 3 * * Sun" */
+  cron: string;
+  /** Folder to sweep; relative paths are under the profile's destination root */
+  folder: string;
+  /** Ids or names of the rules to run, even if they are disabled */
+  rules: string[];
+  /** Profile the rules come from; the default profile when null */
+  profile_id?: string | null;
+  recursive?: RecursiveOptions;
+}
+
+export interface ScheduleStatus {
+  id: string;
+  name: string;
+  enabled: boolean;
+  cron: string;
+  folder: string;
+  last_run: string | null;
+  /** null when disabled or the cron expression is invalid */
+  next_run: string | null;
+  /** Files the last run queued */
+  queued: number;
+  error: string | null;
+}
+
+/** Limits that stop an Extract rule from unpacking a zip bomb */
+export interface ExtractLimits {
+  max_size: number;
+  max_entries: number;
+  /** Most an archive may expand relative to its own size */
+  max_ratio: number;
+}
+
+export type UndoConflict = "Rename" | { RestoreTo: string } | "Fail";
+
+export interface WatchProfile {
+  id: string;
+  name: string;
+  enabled: boolean;
+  watch_folder: string;
+  destination_root: string;
+  grace_period_seconds: number;
+  rules: SortRule[];
+  conflict_resolution: ConflictResolution;
+  recursive?: RecursiveOptions;
+  ignore_patterns?: string[];
+}
+
+export interface RecursiveOptions {
+  enabled: boolean;
+  /** Folder levels below the watch folder to descend into (0 = top level only) */
+  max_depth: number;
+  /** Subfolder names, or paths relative to the watch folder, to skip */
+  exclude: string[];
 }
 
 export interface SortRule {
@@ -21,26 +96,136 @@ export interface SortRule {
   conditions: Condition[];
   destination_folder: string;
   is_default: boolean;
+  /** Only apply to files at most this many folders below the watch folder */
+  max_depth?: number | null;
+  /** Applied in order to the file name before it is moved */
+  rename?: RenameTransform[];
+  action?: RuleAction;
+  /** What an Extract rule does with the archive afterwards */
+  after_extract?: AfterExtract;
+  /** Archive old files in the destination folder into monthly archives */
+  compaction?: Compaction | null;
 }
 
+export interface Compaction {
+  older_than_days: number;
+  format?: CompactionFormat;
+  /** Folder under the destination root the archives go in, "Archive/Old" by default */
+  archive_folder?: string;
+}
+
+export type CompactionFormat = "TarZst" | "Zip";
+
+/** What happens to a matching file; the destination folder is unused by Trash, Delete and Leave */
+export type RuleAction = "Move" | "Copy" | "Hardlink" | "Symlink" | "Extract" | "Trash" | "Delete" | "Leave";
+
+export type AfterExtract =
+  | { type: "Keep" }
+  /** Folder under the destination root; placeholders allowed */
+  | { type: "MoveTo"; value: string }
+  | { type: "Trash" };
+
+export type RenameTransform =
+  | { type: "Lowercase" }
+  | { type: "Uppercase" }
+  | { type: "ReplaceSpaces"; value: string }
+  | { type: "StripCopySuffix" }
+  | { type: "PrefixDate"; value: string }
+  | { type: "RegexReplace"; value: { pattern: string; replacement: string } };
+
 export type Condition =
   | { type: "Extension"; value: string[] }
   | { type: "NameContains"; value: string }
   | { type: "NameRegex"; value: string }
   | { type: "SizeGreaterThan"; value: number }
-  | { type: "SizeLessThan"; value: number };
+  | { type: "SizeLessThan"; value: number }
+  | { type: "OlderThanDays"; value: number }
+  | { type: "NewerThanDays"; value: number }
+  | { type: "ModifiedBefore"; value: string }
+  | { type: "ModifiedAfter"; value: string }
+  | { type: "CreatedBefore"; value: string }
+  | { type: "CreatedAfter"; value: string }
+  | { type: "DayOfWeek"; value: Weekday[] }
+  | { type: "HourBetween"; value: { from: number; to: number } }
+  | { type: "MimeType"; value: string }
+  | { type: "All"; value: Condition[] }
+  | { type: "Any"; value: Condition[] }
+  | { type: "Not"; value: Condition };
 
-export type ConflictResolution = "Rename" | "Skip" | "Overwrite" | "Ask";
+export type Weekday = "Mon" | "Tue" | "Wed" | "Thu" | "Fri" | "Sat" | "Sun";
+
+export type ConflictResolution =
+  | "Rename"
+  | "Skip"
+  | "Overwrite"
+  | "Ask"
+  | { Deduplicate: { duplicates_folder?: string | null } };
 
 export interface PendingFile {
   id: string;
+  profile_id: string;
   path: string;
   file_name: string;
+  /** Name after the rule's rename transforms */
+  target_name: string;
   destination: string;
   rule_name: string;
+  action: RuleAction;
+  after_extract: AfterExtract;
   added_at: number;
   move_at: number;
   file_size: number;
+  state: PendingState;
+  /** Set while state is "NeedsDecision" */
+  conflict: ConflictInfo | null;
+  stable_checks: number;
+  batch_id: string;
+  /** Set when a schedule queued the file */
+  schedule_id: string | null;
+}
+
+export type PendingState = "Waiting" | "WaitingForStable" | "NeedsDecision";
+
+export interface ConflictInfo {
+  existing_path: string;
+  existing_size: number;
+  existing_modified: number | null;
+  incoming_size: number;
+  incoming_modified: number | null;
+}
+
+export type ConflictChoice =
+  | { type: "Rename"; value: string }
+  | { type: "Skip" }
+  | { type: "Overwrite" }
+  | { type: "KeepBoth" };
+
+export type WatcherEvent = { type: "ConflictDetected"; value: PendingFile };
+
+export interface ConflictOutcome {
+  id: string;
+  file_name: string;
+  destination: string | null;
+  error: string | null;
+}
+
+export interface PlannedMove {
+  source: string;
+  /** Final path after conflict handling; null when the move would fail or the file would be trashed or deleted */
+  destination: string | null;
+  rule_name: string;
+  action?: RuleAction;
+  destination_folder: string;
+  error: string | null;
+  /** Existing identical file, when the Deduplicate policy would drop this one */
+  duplicate_of?: string | null;
+}
+
+export interface CompactionSummary {
+  files: number;
+  bytes: number;
+  archives: string[];
+  errors: string[];
 }
 
 export interface MoveRecord {
@@ -51,6 +236,71 @@ export interface MoveRecord {
   timestamp: string;
   file_size: number;
   can_undo: boolean;
+  original_name?: string;
+  new_name?: string;
+  kind?: RecordKind;
+  /** Shared by every move from the same scan, watcher session or cleanup */
+  batch_id?: string | null;
+  /** Modification time (Unix seconds) right after the move */
+  modified?: number | null;
+  /** Profile whose rule made the change */
+  profile_id?: string | null;
+}
+
+export type UndoSelection =
+  | { type: "Ids"; value: string[] }
+  | { type: "Batch"; value: string }
+  | { type: "Rule"; value: string }
+  | { type: "TimeRange"; value: { from: string; to: string } };
+
+export interface UndoOutcome {
+  id: string;
+  /** Where the file was restored; differs from the original path after a conflict */
+  restored_to: string | null;
+  error: string | null;
+}
+
+export type RecordKind =
+  | { type: "Move" }
+  | { type: "Duplicate"; value: { duplicate_of: string; deleted: boolean } }
+  | { type: "DuplicateLinked"; value: { target: string } }
+  | { type: "Copy" }
+  /** new_path is a hard link to original_path */
+  | { type: "Hardlink" }
+  /** new_path is a symbolic link to original_path */
+  | { type: "Symlink" }
+  /** new_path is the file's location in the trash */
+  | { type: "Trashed" }
+  /** Deleted permanently; cannot be undone */
+  | { type: "Deleted" }
+  /** original_path was unpacked into the folder new_path */
+  | { type: "Extracted"; value: { archive_moved_to: string | null; archive_trashed: boolean } }
+  /** Packed into the archive new_path as `entry`, then deleted */
+  | { type: "Compacted"; value: { entry: string } }
+  /** Undid record `of`; while can_undo is true it can be redone */
+  | { type: "Undo"; value: { of: string } };
+
+export interface DuplicateFile {
+  path: string;
+  modified: string | null;
+}
+
+export interface DuplicateGroup {
+  hash: string;
+  size: number;
+  files: DuplicateFile[];
+}
+
+export type DuplicateAction =
+  | { type: "KeepNewest" }
+  | { type: "KeepInFolder"; value: string }
+  | { type: "Hardlink" };
+
+export interface DuplicateOutcome {
+  path: string;
+  kept: string | null;
+  history_id: string | null;
+  error: string | null;
 }
 
 export interface HistoryStats {
@@ -62,4 +312,15 @@ export interface HistoryStats {
 export interface WatcherStatus {
   is_running: boolean;
   is_paused: boolean;
+  profiles: ProfileStatus[];
+}
+
+export interface ProfileStatus {
+  id: string;
+  name: string;
+  watch_folder: string;
+  enabled: boolean;
+  is_watching: boolean;
+  error: string | null;
+  pending_count: number;
 }